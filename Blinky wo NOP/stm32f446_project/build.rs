@@ -0,0 +1,409 @@
+//! Generates register blocks for a few peripherals from the STM32F446 SVD
+//! next to this file, for `src/registers.rs` to include. Each peripheral becomes a module with its base address, a
+//! `#[repr(C)]` block of registers and, per register, the offset, width and
+//! mask of every field. Registers with a typed wrapper in `registers.rs`
+//! take that type; the rest are plain `RW`/`RO`/`WO`.
+//!
+//! The SVD splits multi-bit fields such as PLLM into single bits named
+//! `PLLM0`, `PLLM1`, …; each such run also gets a field spanning it.
+//!
+//! The SVD is plain enough (no `dim` arrays or clusters) that a small tag
+//! scanner reads it without pulling in an XML parser.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The SVD to read, relative to this crate; set `STM32F446_SVD` to read
+/// another.
+const SVD: &str = "stm32f446.svd";
+
+/// Peripherals to generate, by their SVD names.
+const PERIPHERALS: &[&str] = &["RCC", "PWR", "FLASH", "GPIOA", "GPIOC", "TIM6"];
+
+/// Registers that take a typed wrapper from `registers.rs`, by peripheral,
+/// register and wrapper.
+const TYPED: &[(&str, &str, &str)] = &[
+    ("RCC", "CR", "Cr"),
+    ("RCC", "PLLCFGR", "Pllcfgr"),
+    ("RCC", "CFGR", "Cfgr"),
+    ("RCC", "AHB1ENR", "Ahb1enr"),
+    ("RCC", "APB1ENR", "Apb1enr"),
+    ("FLASH", "ACR", "FlashAcr"),
+    ("PWR", "CR", "PwrCr"),
+    ("PWR", "CSR", "PwrCsr"),
+    ("TIM6", "CR1", "TimCr1"),
+    ("TIM6", "SR", "TimSr"),
+];
+
+/// Fields the SVD leaves out, by peripheral, register, name, offset, width
+/// and description.
+const MISSING_FIELDS: &[(&str, &str, &str, u32, u32, &str)] = &[(
+    "RCC",
+    "PLLCFGR",
+    "PLLR",
+    28,
+    3,
+    "Main PLL division factor for I2Ss, SAIs, SYSTEM and SPDIF-Rx clocks",
+)];
+
+struct Field {
+    name: String,
+    description: String,
+    offset: u32,
+    width: u32,
+}
+
+struct Register {
+    name: String,
+    description: String,
+    offset: u32,
+    size: u32,
+    access: String,
+    fields: Vec<Field>,
+}
+
+struct Peripheral {
+    name: String,
+    description: String,
+    base: u32,
+    registers: Vec<Register>,
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=STM32F446_SVD");
+    let manifest = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let svd = env::var("STM32F446_SVD")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| manifest.join(SVD));
+    let out = generate(&svd);
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(Path::new(&out_dir).join("svd.rs"), out).unwrap();
+}
+
+/// The code for every peripheral in [`PERIPHERALS`], from `svd`.
+fn generate(svd: &Path) -> String {
+    println!("cargo:rerun-if-changed={}", svd.display());
+    let xml = fs::read_to_string(svd)
+        .unwrap_or_else(|error| panic!("cannot read {}: {error}", svd.display()));
+    let peripherals: Vec<(String, &str)> = elements(&xml, "peripheral")
+        .map(|(attributes, body)| {
+            (
+                attribute(attributes, "derivedFrom").unwrap_or_default(),
+                body,
+            )
+        })
+        .collect();
+
+    let mut out = String::from("// Generated by build.rs from the STM32F446 SVD. Do not edit.\n");
+    for &name in PERIPHERALS {
+        let mut peripheral = peripherals
+            .iter()
+            .find(|(_, body)| text(body, "name") == Some(name))
+            .map(|(derived_from, body)| {
+                // A derived peripheral takes its registers from another.
+                let registers = if derived_from.is_empty() {
+                    body
+                } else {
+                    peripherals
+                        .iter()
+                        .find(|(_, base)| text(base, "name") == Some(derived_from.as_str()))
+                        .map(|(_, base)| *base)
+                        .unwrap_or_else(|| panic!("{name} derives from unknown {derived_from}"))
+                };
+                parse_peripheral(body, registers)
+            })
+            .unwrap_or_else(|| panic!("{name} is not in {}", svd.display()));
+        patch(&mut peripheral);
+        emit(&mut out, &peripheral);
+    }
+    out
+}
+
+/// Adds [`MISSING_FIELDS`], joins runs of single bits and gives registers
+/// in [`TYPED`] their wrapper.
+fn patch(peripheral: &mut Peripheral) {
+    for register in &mut peripheral.registers {
+        for &(_, _, name, offset, width, description) in MISSING_FIELDS
+            .iter()
+            .filter(|(on, at, ..)| *on == peripheral.name && *at == register.name)
+        {
+            register.fields.push(Field {
+                name: name.to_string(),
+                description: description.to_string(),
+                offset,
+                width,
+            });
+        }
+        join_bits(&mut register.fields);
+
+        if let Some(&(.., typed)) = TYPED
+            .iter()
+            .find(|(on, at, _)| *on == peripheral.name && *at == register.name)
+        {
+            assert!(
+                register.access == "RW" && register.size == 32,
+                "{} {} is not a 32-bit read-write register",
+                peripheral.name,
+                register.name
+            );
+            register.access = typed.to_string();
+        }
+    }
+}
+
+/// Adds a field spanning every run of single bits named `NAME0`, `NAME1`, …
+/// at consecutive offsets, unless the SVD already has a `NAME`.
+fn join_bits(fields: &mut Vec<Field>) {
+    let mut joined = Vec::new();
+    for first in fields.iter().filter(|field| field.width == 1) {
+        let Some(name) = first.name.strip_suffix('0') else {
+            continue;
+        };
+        if name.is_empty()
+            || name.ends_with(|c: char| c.is_ascii_digit())
+            || fields.iter().any(|field| field.name == name)
+        {
+            continue;
+        }
+        let width = (1..)
+            .take_while(|&bit| {
+                fields.iter().any(|field| {
+                    field.name == format!("{name}{bit}")
+                        && field.width == 1
+                        && field.offset == first.offset + bit
+                })
+            })
+            .count() as u32
+            + 1;
+        if width > 1 {
+            joined.push(Field {
+                name: name.to_string(),
+                description: first.description.clone(),
+                offset: first.offset,
+                width,
+            });
+        }
+    }
+    fields.extend(joined);
+}
+
+fn parse_peripheral(body: &str, registers_from: &str) -> Peripheral {
+    let registers = elements(registers_from, "register")
+        .filter(|(_, register)| text(register, "alternateRegister").is_none())
+        .map(|(_, register)| parse_register(register))
+        .collect();
+    Peripheral {
+        name: text(body, "name").unwrap().to_string(),
+        description: text(body, "description")
+            .or_else(|| text(registers_from, "description"))
+            .map(clean)
+            .unwrap_or_default(),
+        base: number(text(body, "baseAddress").unwrap()),
+        registers,
+    }
+}
+
+fn parse_register(body: &str) -> Register {
+    // Everything before <fields> belongs to the register itself.
+    let header = body.split("<fields>").next().unwrap();
+    let fields: Vec<Field> = elements(body, "field")
+        .map(|(_, field)| Field {
+            name: text(field, "name").unwrap().to_string(),
+            description: text(field, "description").map(clean).unwrap_or_default(),
+            offset: number(text(field, "bitOffset").unwrap()),
+            width: number(text(field, "bitWidth").unwrap()),
+        })
+        .collect();
+    let field_access: Vec<&str> = elements(body, "field")
+        .filter_map(|(_, field)| text(field, "access"))
+        .collect();
+    let access = match text(header, "access") {
+        Some(access) => access_type(access),
+        None if !field_access.is_empty()
+            && field_access.iter().all(|&access| access == "read-only") =>
+        {
+            "RO"
+        }
+        None if !field_access.is_empty()
+            && field_access.iter().all(|&access| access == "write-only") =>
+        {
+            "WO"
+        }
+        None => "RW",
+    }
+    .to_string();
+    Register {
+        name: text(header, "name").unwrap().to_string(),
+        description: text(header, "description").map(clean).unwrap_or_default(),
+        offset: number(text(header, "addressOffset").unwrap()),
+        size: text(header, "size").map_or(32, number),
+        access,
+        fields,
+    }
+}
+
+fn emit(out: &mut String, peripheral: &Peripheral) {
+    let module = identifier(&peripheral.name.to_lowercase());
+    let mut registers: Vec<&Register> = peripheral.registers.iter().collect();
+    registers.sort_by_key(|register| register.offset);
+
+    writeln!(out, "\n/// {}", peripheral.description).unwrap();
+    writeln!(out, "pub mod {module} {{").unwrap();
+    writeln!(
+        out,
+        "    pub const BASE: usize = {:#010x};\n",
+        peripheral.base
+    )
+    .unwrap();
+    writeln!(out, "    #[repr(C)]").unwrap();
+    writeln!(out, "    pub struct RegisterBlock {{").unwrap();
+    let mut cursor = 0;
+    let mut reserved = 0;
+    for register in &registers {
+        if register.offset < cursor {
+            // Overlaps one already placed; only its field constants go out.
+            continue;
+        }
+        if register.offset > cursor {
+            writeln!(
+                out,
+                "        _reserved{reserved}: [u8; {}],",
+                register.offset - cursor
+            )
+            .unwrap();
+            reserved += 1;
+        }
+        writeln!(out, "        /// {}", register.description).unwrap();
+        let ty = match register.access.as_str() {
+            access @ ("RW" | "RO" | "WO") => format!("{access}<u{}>", register.size),
+            typed => typed.to_string(),
+        };
+        writeln!(
+            out,
+            "        pub {}: crate::registers::{ty},",
+            identifier(&register.name.to_lowercase())
+        )
+        .unwrap();
+        cursor = register.offset + register.size / 8;
+    }
+    writeln!(out, "    }}").unwrap();
+
+    for register in &registers {
+        writeln!(out, "\n    /// Fields of {}.", register.name).unwrap();
+        writeln!(
+            out,
+            "    pub mod {} {{",
+            identifier(&register.name.to_lowercase())
+        )
+        .unwrap();
+        for field in &register.fields {
+            let name = field.name.to_uppercase();
+            let mask = (u32::MAX >> (32 - field.width)) << field.offset;
+            writeln!(out, "        /// {}", field.description).unwrap();
+            writeln!(
+                out,
+                "        pub const {name}_OFFSET: u8 = {};",
+                field.offset
+            )
+            .unwrap();
+            writeln!(out, "        pub const {name}_WIDTH: u8 = {};", field.width).unwrap();
+            writeln!(out, "        pub const {name}_MASK: u32 = {mask:#010x};").unwrap();
+        }
+        writeln!(out, "    }}").unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+
+    writeln!(
+        out,
+        "\npub const {}: *const {module}::RegisterBlock = {module}::BASE as *const {module}::RegisterBlock;",
+        peripheral.name
+    )
+    .unwrap();
+}
+
+/// The attributes and body of every `<tag>` element in `xml`. SVD never
+/// nests an element in another of the same name.
+fn elements<'a>(xml: &'a str, tag: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+    let open = format!("<{tag}");
+    let close = format!("</{tag}>");
+    let mut rest = xml;
+    std::iter::from_fn(move || {
+        loop {
+            let start = rest.find(&open)?;
+            let after = &rest[start + open.len()..];
+            // Skip longer tags sharing the prefix, like <registers>.
+            if !after.starts_with(['>', ' ', '\n', '\t', '\r']) {
+                rest = after;
+                continue;
+            }
+            let head_end = after.find('>')?;
+            let attributes = &after[..head_end];
+            let body_start = &after[head_end + 1..];
+            let end = body_start.find(&close)?;
+            rest = &body_start[end + close.len()..];
+            return Some((attributes, &body_start[..end]));
+        }
+    })
+}
+
+/// Text of the first `<tag>` in `xml`.
+fn text<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{tag}>");
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&format!("</{tag}>"))?;
+    Some(xml[start..start + end].trim())
+}
+
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let key = format!("{name}=\"");
+    let start = attributes.find(&key)? + key.len();
+    let end = attributes[start..].find('"')?;
+    Some(attributes[start..start + end].to_string())
+}
+
+fn number(text: &str) -> u32 {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).unwrap(),
+        None => text.parse().unwrap(),
+    }
+}
+
+/// Description text on one line with its entities decoded, as a doc
+/// comment needs it.
+fn clean(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn access_type(access: &str) -> &'static str {
+    match access {
+        "read-only" => "RO",
+        "write-only" => "WO",
+        _ => "RW",
+    }
+}
+
+/// `name`, made a valid identifier if it clashes with a keyword.
+fn identifier(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "break", "const", "continue", "crate", "else", "enum", "extern", "fn", "for", "if",
+        "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self",
+        "static", "struct", "super", "trait", "type", "unsafe", "use", "where", "while", "async",
+        "await", "dyn",
+    ];
+    if KEYWORDS.contains(&name) {
+        format!("{name}_")
+    } else {
+        name.to_string()
+    }
+}
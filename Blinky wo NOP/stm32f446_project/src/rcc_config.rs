@@ -1,59 +1,191 @@
-use core::ptr::{read_volatile, write_volatile};
-use crate::registers::RCC;
+use crate::registers::svd::{FLASH, PWR, RCC, pwr};
+use crate::registers::{Hpre, Latency, Pllsrc, Ppre, Sw, Vos};
+use stm32f446_clock::{ClockError, ClockPlan, ClockSource, Clocks, RccConfig, VoltageScale};
 
-// Register addresses
-const RCC_BASE: u32 = 0x40023800;
-const RCC_CR: u32 = RCC_BASE;
-const RCC_CFGR: u32 = RCC_BASE + 0x08;
-const RCC_PLLCFGR: u32 = RCC_BASE + 0x84;
-const RCC_APB1ENR: u32 = RCC_BASE + 0x40;
+/// 180 MHz from the 8 MHz HSE, worked out at build time.
+pub const CLOCK_PLAN: ClockPlan = match RccConfig::new()
+    .use_hse(8_000_000)
+    .sysclk(180_000_000)
+    .freeze()
+{
+    Ok(plan) => plan,
+    Err(_) => panic!("clock configuration cannot be met"),
+};
 
-const FLASH_BASE: u32 = 0x40023C00;
-const FLASH_ACR: u32 = FLASH_BASE;
+/// `CLOCK_PLAN`'s clocks from the HSI, for when the HSE does not start.
+pub const FALLBACK_PLAN: ClockPlan = match CLOCK_PLAN.on_hsi() {
+    Ok(plan) => plan,
+    Err(_) => panic!("clock configuration cannot be met"),
+};
 
-const PWR_BASE: u32 = 0x40007000;
-const PWR_CR: u32 = PWR_BASE;
+/// The HSI alone, which needs nothing to start and cannot fail.
+pub const HSI_PLAN: ClockPlan = match RccConfig::new().freeze() {
+    Ok(plan) => plan,
+    Err(_) => panic!("clock configuration cannot be met"),
+};
 
-pub fn configure_system_clock() {
+/// The fastest the core goes without over-drive, for a regulator that does
+/// not enter it.
+pub const NO_OVERDRIVE_PLAN: ClockPlan = match RccConfig::new()
+    .use_hse(8_000_000)
+    .sysclk(168_000_000)
+    .freeze()
+{
+    Ok(plan) => plan,
+    Err(_) => panic!("clock configuration cannot be met"),
+};
+
+/// `NO_OVERDRIVE_PLAN`'s clocks from the HSI.
+pub const NO_OVERDRIVE_FALLBACK_PLAN: ClockPlan = match NO_OVERDRIVE_PLAN.on_hsi() {
+    Ok(plan) => plan,
+    Err(_) => panic!("clock configuration cannot be met"),
+};
+
+/// Plans `start_clock` tries, best first.
+pub const PLANS: [ClockPlan; 5] = [
+    CLOCK_PLAN,
+    NO_OVERDRIVE_PLAN,
+    FALLBACK_PLAN,
+    NO_OVERDRIVE_FALLBACK_PLAN,
+    HSI_PLAN,
+];
+
+/// Polls of a ready flag before giving up: over 100 ms on the 16 MHz HSI,
+/// where the HSE and PLL need a few milliseconds at most.
+const READY_POLLS: u32 = 400_000;
+
+fn wait_for(ready: impl Fn() -> bool) -> bool {
+    (0..READY_POLLS).any(|_| ready())
+}
+
+/// Brings the clocks up on the first of `PLANS` that starts, or the bare
+/// HSI if none does, and returns that plan.
+pub fn start_clock() -> &'static ClockPlan {
+    PLANS
+        .iter()
+        .find(|plan| configure_system_clock(plan).is_ok())
+        .unwrap_or(&HSI_PLAN)
+}
+
+/// Programs the clocks as `plan` works them out, starting from the HSI.
+/// If the HSE, the PLL or over-drive does not come up in time it is
+/// switched off again, leaving the system clock on the HSI.
+pub fn configure_system_clock(plan: &ClockPlan) -> Result<Clocks, ClockError> {
     unsafe {
+        let rcc = RCC.as_ref().unwrap();
+
         // 1. Enable HSE and wait for it to become ready
-        RCC.as_mut().unwrap().cr.value |= (1 << 16);  // HSEON
-        while (RCC.as_ref().unwrap().cr.value & (1 << 17)) == 0 {}  // HSERDY
+        match plan.source {
+            ClockSource::Hse(_) => {
+                rcc.cr.hseon().set();
+                if !wait_for(|| rcc.cr.hserdy().is_set()) {
+                    rcc.cr.hseon().clear();
+                    return Err(ClockError::HseNotReady);
+                }
+            }
+            ClockSource::Hsi => rcc.cr.hseon().clear(),
+        }
 
         // 2. Enable PWR clock and set voltage regulator
-        RCC.as_mut().unwrap().apb1enr.value |= (1 << 28);  // PWREN
-        
-        let pwr_cr = read_volatile(PWR_CR as *const u32);
-        write_volatile(PWR_CR as *mut u32, pwr_cr | (1 << 14)); // VOS
+        rcc.apb1enr.pwren().set();
+
+        let pwr = PWR.as_ref().unwrap();
+        if !plan.overdrive {
+            disable_overdrive(pwr);
+        }
+        pwr.cr.vos().write(match plan.vos {
+            VoltageScale::Scale3 => Vos::Scale3,
+            VoltageScale::Scale2 => Vos::Scale2,
+            VoltageScale::Scale1 => Vos::Scale1,
+        });
 
         // 3. Configure Flash prefetch and latency
-        let flash_acr = 0x40023C00 as *mut u32;
-        *flash_acr |= (1 << 8) | (1 << 9) | (1 << 10) | (5 << 0);  // ICEN | DCEN | PRFTEN | LATENCY_5WS
+        let flash = FLASH.as_ref().unwrap();
+        flash.acr.icen().set();
+        flash.acr.dcen().set();
+        flash.acr.prften().set();
+        flash.acr.latency().write(Latency::wait_states(plan.flash_latency));
 
         // 4. Configure prescalers
-        // Clear and set prescalers
-        RCC.as_mut().unwrap().cfgr.value &= !(0xF << 4);  // Clear HPRE
-        RCC.as_mut().unwrap().cfgr.value &= !(0x7 << 10);  // Clear PPRE1
-        RCC.as_mut().unwrap().cfgr.value &= !(0x7 << 13);  // Clear PPRE2
-        
-        // Set prescalers
-        RCC.as_mut().unwrap().cfgr.value |= (0x0 << 4);   // HPRE = DIV1
-        RCC.as_mut().unwrap().cfgr.value |= (0x5 << 10);  // PPRE1 = DIV4
-        RCC.as_mut().unwrap().cfgr.value |= (0x4 << 13);  // PPRE2 = DIV2
+        let cfgr = &rcc.cfgr;
+        cfgr.hpre().write(Hpre::divide_by(plan.hpre));
+        cfgr.ppre1().write(Ppre::divide_by(plan.ppre1));
+        cfgr.ppre2().write(Ppre::divide_by(plan.ppre2));
+
+        // Without a PLL the source drives SYSCLK directly
+        let Some(pll) = plan.pll else {
+            let sw = match plan.source {
+                ClockSource::Hsi => Sw::Hsi,
+                ClockSource::Hse(_) => Sw::Hse,
+            };
+            cfgr.sw().write(sw);
+            if !wait_for(|| cfgr.sws().read() == Some(sw)) {
+                cfgr.sw().write(Sw::Hsi);
+                rcc.cr.hseon().clear();
+                return Err(ClockError::HseNotReady);
+            }
+            return Ok(plan.clocks);
+        };
 
         // 5. Configure PLL
-        RCC.as_mut().unwrap().pllcfgr.value = (4 << 0) |    // PLLM = 4
-                              (180 << 6) |    // PLLN = 180
-                              (0 << 16) |     // PLLP = 2
-                              (1 << 22);      // PLLSRC = HSE
+        let pllcfgr = &rcc.pllcfgr;
+        pllcfgr.pllm().write(pll.m);
+        pllcfgr.plln().write(pll.n);
+        pllcfgr.pllp().write(pll.p_bits() as u32);
+        pllcfgr.pllsrc().write(match plan.source {
+            ClockSource::Hsi => Pllsrc::Hsi,
+            ClockSource::Hse(_) => Pllsrc::Hse,
+        });
+        pllcfgr.pllq().write(pll.q);
+        pllcfgr.pllr().write(pll.r);
 
         // 6. Enable PLL and wait for it to become ready
-        RCC.as_mut().unwrap().cr.value |= (1 << 24);  // PLLON
-        while (RCC.as_ref().unwrap().cr.value & (1 << 25)) == 0 {}  // PLLRDY
+        rcc.cr.pllon().set();
+        if !wait_for(|| rcc.cr.pllrdy().is_set()) {
+            rcc.cr.pllon().clear();
+            return Err(ClockError::PllNotReady);
+        }
+
+        // 7. Over-drive for HCLK above 168 MHz, before the switch
+        if plan.overdrive && !enable_overdrive(pwr) {
+            rcc.cr.pllon().clear();
+            disable_overdrive(pwr);
+            return Err(ClockError::OverdriveNotReady);
+        }
+
+        // 8. Select PLL as system clock
+        cfgr.sw().write(Sw::Pll);
+        if !wait_for(|| cfgr.sws().read() == Some(Sw::Pll)) {
+            cfgr.sw().write(Sw::Hsi);
+            rcc.cr.pllon().clear();
+            disable_overdrive(pwr);
+            return Err(ClockError::PllNotReady);
+        }
+    }
+
+    Ok(plan.clocks)
+}
+
+/// Runs the over-drive handshake HCLK above 168 MHz needs. The PLL must
+/// already be running, and the system clock not yet switched to it.
+/// Returns whether the regulator got there in time.
+fn enable_overdrive(pwr: &pwr::RegisterBlock) -> bool {
+    pwr.cr.oden().set();
+    if !wait_for(|| pwr.csr.odrdy().is_set()) {
+        return false;
+    }
+    pwr.cr.odswen().set();
+    wait_for(|| pwr.csr.odswrdy().is_set())
+}
 
-        // 7. Select PLL as system clock
-        RCC.as_mut().unwrap().cfgr.value &= !0x3;  // Clear SW
-        RCC.as_mut().unwrap().cfgr.value |= 0x2;   // SW = PLL
-        while ((RCC.as_ref().unwrap().cfgr.value >> 2) & 0x3) != 0x2 {}  // SWS = PLL
+/// Takes the regulator out of over-drive. The system clock must be off the
+/// PLL. A regulator that does not report leaving in time is left alone;
+/// over-drive costs power but runs any clock.
+fn disable_overdrive(pwr: &pwr::RegisterBlock) {
+    if !pwr.cr.oden().is_set() {
+        return;
     }
-} 
\ No newline at end of file
+    pwr.cr.odswen().clear();
+    pwr.cr.oden().clear();
+    wait_for(|| !pwr.csr.odswrdy().is_set());
+}
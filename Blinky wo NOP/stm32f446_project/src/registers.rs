@@ -1,78 +1,439 @@
-use core::ops::{BitOrAssign, BitAndAssign, Not};
-
-#[repr(C)]
-pub struct Register {
-    pub value: u32,
-}
-
-impl Register {
-    pub const fn new() -> Self {
-        Register { value: 0 }
-    }
-}
-
-impl BitOrAssign<u32> for Register {
-    fn bitor_assign(&mut self, rhs: u32) {
-        self.value |= rhs;
-    }
-}
-
-impl BitAndAssign<u32> for Register {
-    fn bitand_assign(&mut self, rhs: u32) {
-        self.value &= rhs;
-    }
-}
-
-#[repr(C)]
-pub struct RCC {
-    pub cr: Register,
-    pub pllcfgr: Register,
-    pub cfgr: Register,
-    pub cir: Register,
-    pub ahb1rstr: Register,
-    pub ahb2rstr: Register,
-    pub ahb3rstr: Register,
-    _reserved0: [u32; 1],
-    pub apb1rstr: Register,
-    pub apb2rstr: Register,
-    _reserved1: [u32; 2],
-    pub ahb1enr: Register,
-    pub ahb2enr: Register,
-    pub ahb3enr: Register,
-    _reserved2: [u32; 1],
-    pub apb1enr: Register,
-    pub apb2enr: Register,
-}
-
-#[repr(C)]
-pub struct GPIO {
-    pub moder: Register,
-    pub otyper: Register,
-    pub ospeedr: Register,
-    pub pupdr: Register,
-    pub idr: Register,
-    pub odr: Register,
-    pub bsrr: Register,
-    pub lckr: Register,
-    pub afr: [Register; 2],
-}
-
-#[repr(C)]
-pub struct TIMER {
-    pub cr1: Register,
-    pub cr2: Register,
-    pub smcr: Register,
-    pub dier: Register,
-    pub sr: Register,
-    pub egr: Register,
-    pub ccmr1: Register,
-    pub ccmr2: Register,
-    pub ccer: Register,
-    pub cnt: Register,
-    pub psc: Register,
-    pub arr: Register,
-}
-
-pub const RCC: *mut RCC = 0x40023800 as *mut RCC;
-pub const GPIOA: *mut GPIO = 0x40020000 as *mut GPIO;
-pub const TIM6: *mut TIMER = 0x40001000 as *mut TIMER; 
\ No newline at end of file
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use core::ptr::{read_volatile, write_volatile};
+
+/// One memory-mapped register. Every access is a volatile load or store, so
+/// the compiler can never merge, hoist or drop one, and polling loops see
+/// the hardware change the value.
+#[repr(transparent)]
+pub struct Register<T: Copy> {
+    value: UnsafeCell<T>,
+}
+
+impl<T: Copy> Register<T> {
+    fn read(&self) -> T {
+        unsafe { read_volatile(self.value.get()) }
+    }
+
+    fn write(&self, value: T) {
+        unsafe { write_volatile(self.value.get(), value) }
+    }
+}
+
+/// Read-write register.
+#[repr(transparent)]
+pub struct RW<T: Copy>(Register<T>);
+
+impl<T: Copy> RW<T> {
+    pub fn read(&self) -> T {
+        self.0.read()
+    }
+
+    pub fn write(&self, value: T) {
+        self.0.write(value)
+    }
+
+    /// Read, change and write back the value: one load and one store.
+    pub fn modify(&self, f: impl FnOnce(T) -> T) {
+        self.write(f(self.read()));
+    }
+}
+
+/// Read-only register, such as an input data register.
+#[repr(transparent)]
+pub struct RO<T: Copy>(Register<T>);
+
+impl<T: Copy> RO<T> {
+    // Blinky reads no input register yet; the generated blocks still type
+    // theirs as RO so nothing can write one.
+    #[allow(dead_code)]
+    pub fn read(&self) -> T {
+        self.0.read()
+    }
+}
+
+/// Write-only register, such as a set/reset or event register. Reading one
+/// returns nothing useful, so `modify` would be meaningless.
+#[repr(transparent)]
+pub struct WO<T: Copy>(Register<T>);
+
+impl<T: Copy> WO<T> {
+    pub fn write(&self, value: T) {
+        self.0.write(value)
+    }
+}
+
+/// A value a register field can hold, `WIDTH` bits wide.
+pub trait FieldValue: Sized {
+    const WIDTH: u8;
+
+    fn bits(self) -> u32;
+
+    /// `None` for bit patterns the field reserves.
+    fn from_bits(bits: u32) -> Option<Self>;
+}
+
+impl FieldValue for bool {
+    const WIDTH: u8 = 1;
+
+    fn bits(self) -> u32 {
+        self as u32
+    }
+
+    fn from_bits(bits: u32) -> Option<Self> {
+        Some(bits != 0)
+    }
+}
+
+/// `WIDTH` bits at `OFFSET` of a 32-bit register, holding a `V`. Writes
+/// read, change and write back the whole register.
+pub struct Field<'a, V, const OFFSET: u8, const WIDTH: u8> {
+    register: &'a RW<u32>,
+    value: PhantomData<V>,
+}
+
+impl<'a, V, const OFFSET: u8, const WIDTH: u8> Field<'a, V, OFFSET, WIDTH> {
+    const MASK: u32 = (u32::MAX >> (32 - WIDTH)) << OFFSET;
+
+    pub fn new(register: &'a RW<u32>) -> Self {
+        Field {
+            register,
+            value: PhantomData,
+        }
+    }
+
+    /// The field's raw bits.
+    pub fn bits(&self) -> u32 {
+        (self.register.read() & Self::MASK) >> OFFSET
+    }
+
+    /// Writes bits that are already known to fit the field.
+    fn write_bits(&self, bits: u32) {
+        self.register
+            .modify(|value| (value & !Self::MASK) | ((bits << OFFSET) & Self::MASK));
+    }
+}
+
+impl<const OFFSET: u8, const WIDTH: u8> Field<'_, u32, OFFSET, WIDTH> {
+    /// Writes a plain number, for values worked out at runtime such as PLL
+    /// dividers. A number too wide for the field panics rather than being
+    /// cut down to one the hardware would act on.
+    pub fn write(&self, value: u32) {
+        assert!(value <= Self::MASK >> OFFSET, "value does not fit the field");
+        self.write_bits(value);
+    }
+}
+
+impl<V: FieldValue, const OFFSET: u8, const WIDTH: u8> Field<'_, V, OFFSET, WIDTH> {
+    pub fn write(&self, value: V) {
+        const { assert!(V::WIDTH == WIDTH, "value is not as wide as the field") };
+        self.write_bits(value.bits());
+    }
+
+    pub fn read(&self) -> Option<V> {
+        V::from_bits(self.bits())
+    }
+}
+
+impl<const OFFSET: u8> Field<'_, bool, OFFSET, 1> {
+    pub fn set(&self) {
+        self.write(true);
+    }
+
+    pub fn clear(&self) {
+        self.write(false);
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.bits() != 0
+    }
+}
+
+/// Defines a 32-bit read-write register type with named fields, placed by
+/// the offset and width constants in the register's module under [`svd`].
+/// Each field is `name: NAME_OFFSET, NAME_WIDTH => Value`, where `Value` is
+/// `bool` for single bits, an enum from [`field_values!`], or `u32` for
+/// plain numbers whose `write` checks they fit. The register still derefs to
+/// [`RW`] for whole-register access.
+macro_rules! register {
+    ($(#[$meta:meta])* pub struct $name:ident in $peripheral:ident::$register:ident {
+        $($(#[$field_meta:meta])* $field:ident: $offset:ident, $width:ident => $value:ty,)*
+    }) => {
+        $(#[$meta])*
+        #[repr(transparent)]
+        pub struct $name(RW<u32>);
+
+        impl $name {
+            $(
+                $(#[$field_meta])*
+                pub fn $field(
+                    &self,
+                ) -> Field<
+                    '_,
+                    $value,
+                    { svd::$peripheral::$register::$offset },
+                    { svd::$peripheral::$register::$width },
+                > {
+                    Field::new(&self.0)
+                }
+            )*
+        }
+
+        impl Deref for $name {
+            type Target = RW<u32>;
+
+            fn deref(&self) -> &RW<u32> {
+                &self.0
+            }
+        }
+    };
+}
+
+/// Defines the values of a `width`-bit field as an enum. A value that does
+/// not fit the width fails the build.
+macro_rules! field_values {
+    ($(#[$meta:meta])* pub enum $name:ident: $width:literal {
+        $($(#[$variant_meta:meta])* $variant:ident = $bits:literal,)*
+    }) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)*
+        }
+
+        impl FieldValue for $name {
+            const WIDTH: u8 = $width;
+
+            fn bits(self) -> u32 {
+                match self {
+                    $($name::$variant => $bits,)*
+                }
+            }
+
+            fn from_bits(bits: u32) -> Option<Self> {
+                match bits {
+                    $($bits => Some($name::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+
+        $(const _: () = assert!($bits >> $width == 0, "value does not fit the field");)*
+    };
+}
+
+field_values! {
+    /// System clock switch and its status.
+    pub enum Sw: 2 {
+        Hsi = 0b00,
+        Hse = 0b01,
+        Pll = 0b10,
+        PllR = 0b11,
+    }
+}
+
+field_values! {
+    /// AHB prescaler.
+    pub enum Hpre: 4 {
+        Div1 = 0b0000,
+        Div2 = 0b1000,
+        Div4 = 0b1001,
+        Div8 = 0b1010,
+        Div16 = 0b1011,
+        Div64 = 0b1100,
+        Div128 = 0b1101,
+        Div256 = 0b1110,
+        Div512 = 0b1111,
+    }
+}
+
+field_values! {
+    /// APB1 and APB2 prescalers.
+    pub enum Ppre: 3 {
+        Div1 = 0b000,
+        Div2 = 0b100,
+        Div4 = 0b101,
+        Div8 = 0b110,
+        Div16 = 0b111,
+    }
+}
+
+impl Hpre {
+    /// The prescaler dividing by `divisor`, which must be one of the
+    /// powers of two the field offers.
+    pub const fn divide_by(divisor: u32) -> Self {
+        match divisor {
+            1 => Hpre::Div1,
+            2 => Hpre::Div2,
+            4 => Hpre::Div4,
+            8 => Hpre::Div8,
+            16 => Hpre::Div16,
+            64 => Hpre::Div64,
+            128 => Hpre::Div128,
+            256 => Hpre::Div256,
+            512 => Hpre::Div512,
+            _ => panic!("no AHB prescaler for this divisor"),
+        }
+    }
+}
+
+impl Ppre {
+    /// The prescaler dividing by `divisor`, one of 1, 2, 4, 8 or 16.
+    pub const fn divide_by(divisor: u32) -> Self {
+        match divisor {
+            1 => Ppre::Div1,
+            2 => Ppre::Div2,
+            4 => Ppre::Div4,
+            8 => Ppre::Div8,
+            16 => Ppre::Div16,
+            _ => panic!("no APB prescaler for this divisor"),
+        }
+    }
+}
+
+field_values! {
+    /// Flash wait states, as wide as the SVD gives FLASH_ACR LATENCY.
+    pub enum Latency: 3 {
+        Ws0 = 0,
+        Ws1 = 1,
+        Ws2 = 2,
+        Ws3 = 3,
+        Ws4 = 4,
+        Ws5 = 5,
+        Ws6 = 6,
+        Ws7 = 7,
+    }
+}
+
+impl Latency {
+    pub const fn wait_states(count: u8) -> Self {
+        match count {
+            0 => Latency::Ws0,
+            1 => Latency::Ws1,
+            2 => Latency::Ws2,
+            3 => Latency::Ws3,
+            4 => Latency::Ws4,
+            5 => Latency::Ws5,
+            6 => Latency::Ws6,
+            7 => Latency::Ws7,
+            _ => panic!("more wait states than the field holds"),
+        }
+    }
+}
+
+field_values! {
+    /// Regulator voltage scale; 0b00 is reserved.
+    pub enum Vos: 2 {
+        Scale3 = 0b01,
+        Scale2 = 0b10,
+        Scale1 = 0b11,
+    }
+}
+
+field_values! {
+    /// PLL input.
+    pub enum Pllsrc: 1 {
+        Hsi = 0,
+        Hse = 1,
+    }
+}
+
+register! {
+    /// RCC clock control register.
+    pub struct Cr in rcc::cr {
+        hseon: HSEON_OFFSET, HSEON_WIDTH => bool,
+        hserdy: HSERDY_OFFSET, HSERDY_WIDTH => bool,
+        pllon: PLLON_OFFSET, PLLON_WIDTH => bool,
+        pllrdy: PLLRDY_OFFSET, PLLRDY_WIDTH => bool,
+    }
+}
+
+register! {
+    /// RCC main PLL configuration register.
+    pub struct Pllcfgr in rcc::pllcfgr {
+        pllm: PLLM_OFFSET, PLLM_WIDTH => u32,
+        plln: PLLN_OFFSET, PLLN_WIDTH => u32,
+        pllp: PLLP_OFFSET, PLLP_WIDTH => u32,
+        pllsrc: PLLSRC_OFFSET, PLLSRC_WIDTH => Pllsrc,
+        pllq: PLLQ_OFFSET, PLLQ_WIDTH => u32,
+        pllr: PLLR_OFFSET, PLLR_WIDTH => u32,
+    }
+}
+
+register! {
+    /// RCC clock configuration register.
+    pub struct Cfgr in rcc::cfgr {
+        sw: SW_OFFSET, SW_WIDTH => Sw,
+        sws: SWS_OFFSET, SWS_WIDTH => Sw,
+        hpre: HPRE_OFFSET, HPRE_WIDTH => Hpre,
+        ppre1: PPRE1_OFFSET, PPRE1_WIDTH => Ppre,
+        ppre2: PPRE2_OFFSET, PPRE2_WIDTH => Ppre,
+    }
+}
+
+register! {
+    /// RCC AHB1 peripheral clock enable register.
+    pub struct Ahb1enr in rcc::ahb1enr {
+        gpioaen: GPIOAEN_OFFSET, GPIOAEN_WIDTH => bool,
+    }
+}
+
+register! {
+    /// RCC APB1 peripheral clock enable register.
+    pub struct Apb1enr in rcc::apb1enr {
+        tim6en: TIM6EN_OFFSET, TIM6EN_WIDTH => bool,
+        pwren: PWREN_OFFSET, PWREN_WIDTH => bool,
+    }
+}
+
+register! {
+    /// Flash access control register.
+    pub struct FlashAcr in flash::acr {
+        latency: LATENCY_OFFSET, LATENCY_WIDTH => Latency,
+        prften: PRFTEN_OFFSET, PRFTEN_WIDTH => bool,
+        icen: ICEN_OFFSET, ICEN_WIDTH => bool,
+        dcen: DCEN_OFFSET, DCEN_WIDTH => bool,
+    }
+}
+
+register! {
+    /// PWR power control register.
+    pub struct PwrCr in pwr::cr {
+        vos: VOS_OFFSET, VOS_WIDTH => Vos,
+        oden: ODEN_OFFSET, ODEN_WIDTH => bool,
+        odswen: ODSWEN_OFFSET, ODSWEN_WIDTH => bool,
+    }
+}
+
+register! {
+    /// PWR power control/status register.
+    pub struct PwrCsr in pwr::csr {
+        odrdy: ODRDY_OFFSET, ODRDY_WIDTH => bool,
+        odswrdy: ODSWRDY_OFFSET, ODSWRDY_WIDTH => bool,
+    }
+}
+
+register! {
+    /// Timer control register 1.
+    pub struct TimCr1 in tim6::cr1 {
+        cen: CEN_OFFSET, CEN_WIDTH => bool,
+    }
+}
+
+register! {
+    /// Timer status register. Flags clear by writing 0.
+    pub struct TimSr in tim6::sr {
+        uif: UIF_OFFSET, UIF_WIDTH => bool,
+    }
+}
+
+/// Register blocks that build.rs generates from the STM32F446 SVD: per
+/// peripheral a `RegisterBlock` of the register types above, its `BASE`
+/// and a pointer to it and, per register, every field's `_OFFSET`, `_WIDTH`
+/// and `_MASK`, ready for [`Field`]. Every register and field the SVD has
+/// is generated, so most go unused.
+#[allow(dead_code)]
+pub mod svd {
+    include!(concat!(env!("OUT_DIR"), "/svd.rs"));
+}
@@ -1,28 +1,36 @@
-use crate::registers::{RCC, TIM6};
+use crate::registers::svd::{RCC, TIM6};
+use stm32f446_clock::{Clocks, timer_prescaler};
 
-pub fn configure_timer() {
+pub fn configure_timer(clocks: &Clocks) {
     unsafe {
         // Enable Timer clock
-        RCC.as_mut().unwrap().apb1enr.value |= (1 << 4);  // TIM6EN
+        RCC.as_ref().unwrap().apb1enr.tim6en().set();
 
         // Configure timer
         
-        TIM6.as_mut().unwrap().psc.value = 90 - 1;  // 90MHz/90 = 1MHz
-        TIM6.as_mut().unwrap().arr.value = 0xFFFF;  // Max ARR value
+        retime_timer(clocks);  // 1MHz
+        TIM6.as_ref().unwrap().arr.write(0xFFFF);  // Max ARR value
         
         // Enable counter and wait for update flag
-        TIM6.as_mut().unwrap().cr1.value |= (1 << 0);  // CEN
-        while (TIM6.as_ref().unwrap().sr.value & (1 << 0)) == 0 {}  // UIF
+        TIM6.as_ref().unwrap().cr1.cen().set();
+        while !TIM6.as_ref().unwrap().sr.uif().is_set() {}
+    }
+}
+
+/// Keeps the delays in microseconds at the clocks given.
+pub fn retime_timer(clocks: &Clocks) {
+    unsafe {
+        TIM6.as_ref().unwrap().psc.write(timer_prescaler(clocks.timclk1(), 1_000_000));
     }
 }
 
 pub fn delay_us(us: u16) {
     unsafe {
         // Reset counter
-        TIM6.as_mut().unwrap().cnt.value = 0;
+        TIM6.as_ref().unwrap().cnt.write(0);
         
         // Wait for counter to reach desired value
-        while TIM6.as_ref().unwrap().cnt.value < us as u32 {}
+        while TIM6.as_ref().unwrap().cnt.read() < us as u32 {}
     }
 }
 
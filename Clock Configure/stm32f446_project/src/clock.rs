@@ -1,52 +1,294 @@
 #![allow(dead_code)]
 
 use stm32f4::stm32f446;
+use stm32f446_clock::{Clock48Source, ClockError, ClockPlan, ClockSource, Clocks, RccConfig};
 
-/// Initialize system clock to 180 MHz using HSE and PLL.
-pub fn init_clock() {
+/// 180 MHz from the 8 MHz ST-LINK MCO on HSE, and the 48 MHz USB clock,
+/// worked out at build time.
+pub const CLOCK_PLAN: ClockPlan = match RccConfig::new()
+    .use_hse(8_000_000)
+    .sysclk(180_000_000)
+    .require_pll48clk()
+    .freeze()
+{
+    Ok(plan) => plan,
+    Err(_) => panic!("clock configuration cannot be met"),
+};
+
+/// `CLOCK_PLAN`'s clocks from the HSI, for when the HSE does not start.
+pub const FALLBACK_PLAN: ClockPlan = match CLOCK_PLAN.on_hsi() {
+    Ok(plan) => plan,
+    Err(_) => panic!("clock configuration cannot be met"),
+};
+
+/// The HSI alone, which needs nothing to start and cannot fail.
+pub const HSI_PLAN: ClockPlan = match RccConfig::new().freeze() {
+    Ok(plan) => plan,
+    Err(_) => panic!("clock configuration cannot be met"),
+};
+
+/// Plans `start_clock` tries, best first.
+pub const PLANS: [ClockPlan; 3] = [CLOCK_PLAN, FALLBACK_PLAN, HSI_PLAN];
+
+/// Polls of a ready flag before giving up: over 100 ms on the 16 MHz HSI,
+/// where the HSE and PLLs need a few milliseconds at most.
+const READY_POLLS: u32 = 400_000;
+
+fn wait_for(ready: impl Fn() -> bool) -> bool {
+    (0..READY_POLLS).any(|_| ready())
+}
+
+/// Bring the clocks up on the first of `PLANS` that starts, or the bare
+/// HSI if none does, and return that plan. Its `source` tells whether the
+/// board runs from the HSE.
+pub fn start_clock() -> &'static ClockPlan {
+    PLANS
+        .iter()
+        .find(|plan| init_clock(plan).is_ok())
+        .unwrap_or(&HSI_PLAN)
+}
+
+/// Initialize the system clock as `plan` describes and return the clocks
+/// it runs at. If the HSE, a PLL or over-drive does not come up in time it
+/// is switched off again, leaving the system clock on the HSI.
+pub fn init_clock(plan: &ClockPlan) -> Result<Clocks, ClockError> {
     let dp = unsafe { stm32f446::Peripherals::steal() }; // SAFETY: We are early in startup, only one caller
 
     let rcc = &dp.RCC;
     let pwr = &dp.PWR;
     let flash = &dp.FLASH;
 
+    // 0. Run from the HSI with every PLL off, so nothing is left over from
+    //    a plan that failed part-way. The HSI is on-chip and cannot fail to
+    //    start, and switching to it takes a few cycles, so these two waits
+    //    need no bound
+    rcc.cr.modify(|_, w| w.hsion().on());
+    while rcc.cr.read().hsirdy().is_not_ready() {}
+    rcc.cfgr.modify(|_, w| w.sw().hsi());
+    while !rcc.cfgr.read().sws().is_hsi() {}
+    rcc.cr.modify(|_, w| w.pllon().off()
+                          .pllsaion().off()
+                          .plli2son().off());
+    pwr.cr.modify(|_, w| w.odswen().clear_bit()
+                          .oden().clear_bit());
+
     // 1. Enable HSE and wait for ready
-    rcc.cr.modify(|_, w| w.hseon().on());
-    while rcc.cr.read().hserdy().is_not_ready() {}
+    if let ClockSource::Hse(_) = plan.source {
+        rcc.cr.modify(|_, w| w.hseon().on());
+        if !wait_for(|| rcc.cr.read().hserdy().is_ready()) {
+            rcc.cr.modify(|_, w| w.hseon().off());
+            return Err(ClockError::HseNotReady);
+        }
+    } else {
+        rcc.cr.modify(|_, w| w.hseon().off());
+    }
 
     // 2. Power enable clock and voltage regulator
     rcc.apb1enr.modify(|_, w| w.pwren().enabled());
-    //pwr.cr.modify(|_, w| w.vos().scale1());
-    pwr.cr.modify(|_, w| w.vos().variant(0b11)); // Scale 1 is usually 0b11 or 0b01
+    pwr.cr.modify(|_, w| w.vos().variant(plan.vos.bits()));
 
     // 3. Configure flash
     flash.acr.modify(|_, w| {
         w.icen().enabled();
         w.dcen().enabled();
         w.prften().enabled();
-        w.latency().ws5()
+        w.latency().bits(plan.flash_latency)
     });
 
     // 4. Prescalers
-    rcc.cfgr.modify(|_, w| {
-        w.hpre().div1();   // AHB = SYSCLK
-        w.ppre1().div4();  // APB1 = SYSCLK/4
-        w.ppre2().div2()   // APB2 = SYSCLK/2
+    rcc.cfgr.modify(|_, w| unsafe {
+        w.hpre().bits(plan.hpre_bits());
+        w.ppre1().bits(plan.ppre1_bits());
+        w.ppre2().bits(plan.ppre2_bits())
+    });
+
+    // 5. PLL input, shared by all three PLLs
+    rcc.pllcfgr.modify(|_, w| match plan.source {
+        ClockSource::Hsi => w.pllsrc().hsi(),
+        ClockSource::Hse(_) => w.pllsrc().hse(),
     });
 
-    // 5. Configure PLL (M=4, N=180, P=2)
-    rcc.pllcfgr.write(|w| unsafe {
-        w.pllm().bits(4)
-         .plln().bits(180)
-         .pllp().div2()
-         .pllsrc().hse()
+    // 6. PLLSAI and PLLI2S, and where the 48 MHz clock comes from
+    if let Some(sai) = plan.pllsai {
+        rcc.pllsaicfgr.write(|w| unsafe {
+            w.pllsaim().bits(sai.m as u8)
+             .pllsain().bits(sai.n as u16)
+             .pllsaip().bits(sai.p_bits())
+             .pllsaiq().bits(sai.q as u8)
+        });
+        rcc.cr.modify(|_, w| w.pllsaion().on());
+        if !wait_for(|| rcc.cr.read().pllsairdy().is_ready()) {
+            rcc.cr.modify(|_, w| w.pllsaion().off());
+            return Err(ClockError::PllNotReady);
+        }
+    }
+    if let Some(i2s) = plan.plli2s {
+        rcc.plli2scfgr.write(|w| unsafe {
+            w.plli2sm().bits(i2s.m as u8)
+             .plli2sn().bits(i2s.n as u16)
+             .plli2sp().bits(i2s.p_bits())
+             .plli2sq().bits(i2s.q as u8)
+             .plli2sr().bits(i2s.r as u8)
+        });
+        rcc.cr.modify(|_, w| w.plli2son().on());
+        if !wait_for(|| rcc.cr.read().plli2srdy().is_ready()) {
+            rcc.cr.modify(|_, w| w.plli2son().off());
+            return Err(ClockError::PllNotReady);
+        }
+    }
+    match plan.clock48 {
+        Some(Clock48Source::PllSaiP) => rcc.dckcfgr2.modify(|_, w| w.ck48msel().pllsai()),
+        _ => rcc.dckcfgr2.modify(|_, w| w.ck48msel().pll()),
+    }
+
+    // Without a PLL the source drives SYSCLK directly
+    let Some(pll) = plan.pll else {
+        rcc.cfgr.modify(|_, w| match plan.source {
+            ClockSource::Hsi => w.sw().hsi(),
+            ClockSource::Hse(_) => w.sw().hse(),
+        });
+        return Ok(plan.clocks);
+    };
+
+    // 7. Configure PLL
+    rcc.pllcfgr.modify(|_, w| unsafe {
+        w.pllm().bits(pll.m as u8)
+         .plln().bits(pll.n as u16)
+         .pllp().bits(pll.p_bits())
+         .pllq().bits(pll.q as u8)
+         .pllr().bits(pll.r as u8)
     });
 
-    // 6. Enable PLL and wait for ready
+    // 8. Enable PLL and wait for ready
     rcc.cr.modify(|_, w| w.pllon().on());
-    while rcc.cr.read().pllrdy().is_not_ready() {}
+    if !wait_for(|| rcc.cr.read().pllrdy().is_ready()) {
+        rcc.cr.modify(|_, w| w.pllon().off());
+        return Err(ClockError::PllNotReady);
+    }
 
-    // 7. Switch system clock source to PLL
+    // 9. Over-drive for HCLK above 168 MHz, before the switch
+    if plan.overdrive {
+        pwr.cr.modify(|_, w| w.oden().set_bit());
+        let ready = wait_for(|| pwr.csr.read().odrdy().bit_is_set()) && {
+            pwr.cr.modify(|_, w| w.odswen().set_bit());
+            wait_for(|| pwr.csr.read().odswrdy().bit_is_set())
+        };
+        if !ready {
+            rcc.cr.modify(|_, w| w.pllon().off());
+            pwr.cr.modify(|_, w| w.odswen().clear_bit()
+                                  .oden().clear_bit());
+            return Err(ClockError::OverdriveNotReady);
+        }
+    }
+
+    // 10. Switch system clock source to PLL
     rcc.cfgr.modify(|_, w| w.sw().pll());
-    while !rcc.cfgr.read().sws().is_pll() {}
+    if !wait_for(|| rcc.cfgr.read().sws().is_pll()) {
+        rcc.cfgr.modify(|_, w| w.sw().hsi());
+        rcc.cr.modify(|_, w| w.pllon().off());
+        pwr.cr.modify(|_, w| w.odswen().clear_bit()
+                              .oden().clear_bit());
+        return Err(ClockError::PllNotReady);
+    }
+
+    Ok(plan.clocks)
+}
+
+/// Clocks MCO1 (PA8) can output.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mco1Source {
+    Hsi,
+    /// Only once the LSE has been started in the backup domain.
+    Lse,
+    Hse,
+    Pll,
+}
+
+/// Clocks MCO2 (PC9) can output.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mco2Source {
+    Sysclk,
+    /// Only once the PLLI2S has been started.
+    PllI2s,
+    Hse,
+    Pll,
+}
+
+/// Divider between the selected clock and its MCO pin. The GPIO tops out
+/// around 100 MHz, so divide the PLL and SYSCLK down before measuring them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum McoPrescaler {
+    Div1,
+    Div2,
+    Div3,
+    Div4,
+    Div5,
+}
+
+impl McoPrescaler {
+    /// Value of the RCC_CFGR MCO1PRE and MCO2PRE fields.
+    pub const fn bits(self) -> u8 {
+        match self {
+            McoPrescaler::Div1 => 0b000,
+            McoPrescaler::Div2 => 0b100,
+            McoPrescaler::Div3 => 0b101,
+            McoPrescaler::Div4 => 0b110,
+            McoPrescaler::Div5 => 0b111,
+        }
+    }
+}
+
+/// Put `pin` of `gpio` on alternate function 0 at very high speed, where
+/// both MCO pins find their output.
+macro_rules! mco_pin {
+    ($gpio:expr, $pin:expr) => {{
+        let shift = $pin * 2;
+        $gpio
+            .moder
+            .modify(|r, w| unsafe { w.bits((r.bits() & !(0b11 << shift)) | (0b10 << shift)) });
+        $gpio
+            .ospeedr
+            .modify(|r, w| unsafe { w.bits(r.bits() | (0b11 << shift)) });
+        let afr_shift = ($pin % 8) * 4;
+        $gpio
+            .afrh
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(0xF << afr_shift)) });
+    }};
+}
+
+/// Output `source` divided by `prescaler` on MCO1 (PA8), to check a clock
+/// with a scope or frequency counter.
+pub fn mco1(source: Mco1Source, prescaler: McoPrescaler) {
+    let dp = unsafe { stm32f446::Peripherals::steal() };
+
+    dp.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled());
+    mco_pin!(dp.GPIOA, 8);
+
+    dp.RCC.cfgr.modify(|_, w| {
+        unsafe { w.mco1pre().bits(prescaler.bits()) };
+        match source {
+            Mco1Source::Hsi => w.mco1().hsi(),
+            Mco1Source::Lse => w.mco1().lse(),
+            Mco1Source::Hse => w.mco1().hse(),
+            Mco1Source::Pll => w.mco1().pll(),
+        }
+    });
+}
+
+/// Output `source` divided by `prescaler` on MCO2 (PC9).
+pub fn mco2(source: Mco2Source, prescaler: McoPrescaler) {
+    let dp = unsafe { stm32f446::Peripherals::steal() };
+
+    dp.RCC.ahb1enr.modify(|_, w| w.gpiocen().enabled());
+    mco_pin!(dp.GPIOC, 9);
+
+    dp.RCC.cfgr.modify(|_, w| {
+        unsafe { w.mco2pre().bits(prescaler.bits()) };
+        match source {
+            Mco2Source::Sysclk => w.mco2().sysclk(),
+            Mco2Source::PllI2s => w.mco2().plli2s(),
+            Mco2Source::Hse => w.mco2().hse(),
+            Mco2Source::Pll => w.mco2().pll(),
+        }
+    });
 }
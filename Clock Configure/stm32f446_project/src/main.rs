@@ -4,6 +4,7 @@
 use cortex_m_rt::entry;
 use cortex_m::asm;
 mod clock; // bring clock.rs module
+mod measure;
 use core::panic::PanicInfo;
 
 #[panic_handler]
@@ -13,7 +14,31 @@ fn panic(_info: &PanicInfo) -> ! {
 
 #[entry]
 fn main() -> ! {
-    clock::init_clock(); // Call our clock initialization
+    // Trim the HSI against the LSE crystal first, from the HSI alone, so
+    // that a fallback to it runs as close to its clocks as HSITRIM allows.
+    let lse = measure::start_lse();
+    let _hsi = clock::init_clock(&clock::HSI_PLAN)
+        .ok()
+        .filter(|_| lse)
+        .and_then(|_| measure::trim_hsi(&clock::HSI_PLAN));
+
+    // Call our clock initialization. `_source` shows whether the HSE came
+    // up or the board fell back to the HSI.
+    let plan = clock::start_clock();
+    let clocks = plan.clocks;
+    let _source = plan.source;
+
+    // Self-test: the timer clock against the LSE crystal, the LSI and the
+    // HSE against the timer clock. Inspect them with the debugger.
+    let _timer_clock = lse.then(|| measure::check_timer_clock(&clocks)).flatten();
+    let _lsi = measure::start_lsi()
+        .then(|| measure::measure_lsi(&clocks))
+        .flatten();
+    let _hse = measure::measure_hse(plan);
+
+    // PLL / 4 = 45 MHz on PA8 and SYSCLK / 5 = 36 MHz on PC9 for a scope
+    clock::mco1(clock::Mco1Source::Pll, clock::McoPrescaler::Div4);
+    clock::mco2(clock::Mco2Source::Sysclk, clock::McoPrescaler::Div5);
 
     loop {
         asm::nop();
@@ -0,0 +1,184 @@
+#![allow(dead_code)]
+
+use stm32f4::stm32f446;
+use stm32f446_clock::{
+    ClockPlan, ClockSource, Clocks, HSI_HZ, LSE_HZ, LSI_HZ, Measurement, hsi_trim,
+};
+
+// Each capture spans 8 input cycles (ICxPSC = /8); a measurement adds up
+// 16 of them.
+const CYCLES_PER_CAPTURE: u32 = 8;
+const CAPTURES: u32 = 16;
+const CYCLES: u32 = CYCLES_PER_CAPTURE * CAPTURES;
+
+/// Polls of a ready or capture flag before giving up on a clock that is not
+/// running. The LSE crystal alone can take two seconds to start.
+const POLLS: u32 = 50_000_000;
+
+/// HSE divider for the HSE_RTC input of TIM11; 8 MHz comes out at 1 MHz.
+const HSE_RTC_DIV: u32 = 8;
+
+/// Most trimming passes before settling for the closest so far.
+const TRIM_PASSES: u32 = 8;
+
+/// Clocks TIM5 CH4 can capture in place of its pin.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tim5Input {
+    Lsi,
+    Lse,
+}
+
+fn wait_for(ready: impl Fn() -> bool) -> bool {
+    (0..POLLS).any(|_| ready())
+}
+
+/// Start the LSI and wait for it.
+pub fn start_lsi() -> bool {
+    let dp = unsafe { stm32f446::Peripherals::steal() };
+
+    dp.RCC.csr.modify(|_, w| w.lsion().on());
+    wait_for(|| dp.RCC.csr.read().lsirdy().is_ready())
+}
+
+/// Start the LSE crystal in the backup domain and wait for it.
+pub fn start_lse() -> bool {
+    let dp = unsafe { stm32f446::Peripherals::steal() };
+
+    // The backup domain is write-protected until DBP is set
+    dp.RCC.apb1enr.modify(|_, w| w.pwren().enabled());
+    dp.PWR.cr.modify(|_, w| w.dbp().set_bit());
+    dp.RCC.bdcr.modify(|_, w| w.lseon().on());
+    wait_for(|| dp.RCC.bdcr.read().lserdy().is_ready())
+}
+
+/// Ticks of TIM5's clock across `CYCLES` cycles of `input` on CH4.
+fn capture_tim5(input: Tim5Input) -> Option<u32> {
+    let dp = unsafe { stm32f446::Peripherals::steal() };
+    let tim5 = &dp.TIM5;
+
+    dp.RCC.apb1enr.modify(|_, w| w.tim5en().enabled());
+    tim5.or.write(|w| unsafe {
+        w.it4_rmp().bits(match input {
+            Tim5Input::Lsi => 0b01,
+            Tim5Input::Lse => 0b10,
+        })
+    });
+    tim5.ccmr2_input()
+        .write(|w| unsafe { w.cc4s().bits(0b01).ic4psc().bits(0b11) });
+    tim5.ccer.write(|w| w.cc4e().set_bit());
+    tim5.psc.write(|w| w.psc().bits(0));
+    tim5.arr.write(|w| w.arr().bits(u32::MAX));
+    tim5.cr1.modify(|_, w| w.cen().set_bit());
+
+    // Reading CCR4 clears CC4IF
+    let capture = || {
+        wait_for(|| tim5.sr.read().cc4if().bit_is_set()).then(|| tim5.ccr4().read().ccr().bits())
+    };
+    let ticks = (|| {
+        let first = capture()?;
+        let mut last = first;
+        for _ in 0..CAPTURES {
+            last = capture()?;
+        }
+        Some(last.wrapping_sub(first))
+    })();
+
+    tim5.cr1.modify(|_, w| w.cen().clear_bit());
+    dp.RCC.apb1enr.modify(|_, w| w.tim5en().disabled());
+    ticks
+}
+
+/// Ticks of TIM11's clock across `CYCLES` cycles of HSE_RTC on CH1. The
+/// counter is only 16 bits, so the captures are added up one by one.
+fn capture_tim11() -> Option<u32> {
+    let dp = unsafe { stm32f446::Peripherals::steal() };
+    let tim11 = &dp.TIM11;
+
+    dp.RCC
+        .cfgr
+        .modify(|_, w| w.rtcpre().bits(HSE_RTC_DIV as u8));
+    dp.RCC.apb2enr.modify(|_, w| w.tim11en().enabled());
+    tim11.or.write(|w| unsafe { w.rmp().bits(0b10) }); // HSE_RTC
+    tim11
+        .ccmr1_input()
+        .write(|w| unsafe { w.cc1s().bits(0b01).ic1psc().bits(0b11) });
+    tim11.ccer.write(|w| w.cc1e().set_bit());
+    tim11.psc.write(|w| w.psc().bits(0));
+    tim11.arr.write(|w| unsafe { w.arr().bits(u16::MAX) });
+    tim11.cr1.modify(|_, w| w.cen().set_bit());
+
+    let capture = || {
+        wait_for(|| tim11.sr.read().cc1if().bit_is_set()).then(|| tim11.ccr1().read().ccr().bits())
+    };
+    let ticks = (|| {
+        let mut last = capture()?;
+        let mut ticks = 0;
+        for _ in 0..CAPTURES {
+            let next = capture()?;
+            ticks += u32::from(next.wrapping_sub(last));
+            last = next;
+        }
+        Some(ticks)
+    })();
+
+    tim11.cr1.modify(|_, w| w.cen().clear_bit());
+    dp.RCC.apb2enr.modify(|_, w| w.tim11en().disabled());
+    ticks
+}
+
+/// The APB1 timer clock against the LSE, which the rest of `clocks` is
+/// divided down from alongside it. `None` if the LSE is not running or
+/// nothing was captured.
+pub fn check_timer_clock(clocks: &Clocks) -> Option<Measurement> {
+    let ticks = capture_tim5(Tim5Input::Lse)?;
+    Measurement::of_counter(clocks.timclk1(), LSE_HZ, CYCLES, ticks)
+}
+
+/// The LSI against the APB1 timer clock, taking `clocks` as right.
+pub fn measure_lsi(clocks: &Clocks) -> Option<Measurement> {
+    let ticks = capture_tim5(Tim5Input::Lsi)?;
+    Measurement::of_input(LSI_HZ, clocks.timclk1(), CYCLES, ticks)
+}
+
+/// The HSE against the APB2 timer clock, taking `clocks` as right. `None`
+/// if the plan does not run the HSE.
+pub fn measure_hse(plan: &ClockPlan) -> Option<Measurement> {
+    let ClockSource::Hse(hse_hz) = plan.source else {
+        return None;
+    };
+    let ticks = capture_tim11()?;
+    let rtc = Measurement::of_input(hse_hz / HSE_RTC_DIV, plan.clocks.timclk2(), CYCLES, ticks)?;
+    Some(Measurement {
+        nominal: hse_hz,
+        measured: rtc.measured * HSE_RTC_DIV,
+    })
+}
+
+/// Trim the HSI as close to 16 MHz as HSITRIM goes, measuring it against
+/// the LSE through the clocks `plan` derives from it. Returns the HSI as
+/// last measured, or `None` if the plan does not run from the HSI or the
+/// LSE is not running.
+pub fn trim_hsi(plan: &ClockPlan) -> Option<Measurement> {
+    let ClockSource::Hsi = plan.source else {
+        return None;
+    };
+    let dp = unsafe { stm32f446::Peripherals::steal() };
+
+    let mut hsi = Measurement {
+        nominal: HSI_HZ,
+        measured: HSI_HZ,
+    };
+    for _ in 0..TRIM_PASSES {
+        // The timer clock is a fixed multiple of the HSI
+        let timer = check_timer_clock(&plan.clocks)?;
+        hsi.measured = (HSI_HZ as u64 * timer.measured as u64 / timer.nominal as u64) as u32;
+
+        let trim = dp.RCC.cr.read().hsitrim().bits();
+        let next = hsi_trim(trim, &hsi);
+        if next == trim {
+            break;
+        }
+        dp.RCC.cr.modify(|_, w| w.hsitrim().bits(next));
+    }
+    Some(hsi)
+}
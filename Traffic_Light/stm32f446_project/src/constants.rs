@@ -7,24 +7,30 @@ pub const YELLOW_RIGHT: u16 = 11;
 pub const RED_RIGHT: u16 = 12;
 pub const LEFT_TRAFFIC_INTENSITY: u16 = 4;
 pub const RIGHT_TRAFFIC_INSTENSITY: u16 = 7;
+pub const RIGHT_SECOND_LANE_DETECTOR: u16 = 3;
 pub const LEFT_TRAFFIC_INDICATOR: u16 = 5;
 pub const RIGHT_TRAFFIC_INDICATOR: u16 = 15;
+pub const PED_BUTTON: u16 = 0;
+pub const PED_WALK: u16 = 1;
+pub const PED_DONT_WALK: u16 = 2;
+// User button B1, on GPIOC
+pub const FLASH_BUTTON: u16 = 13;
+// Emergency preemption receiver for the right road, on GPIOC
+pub const PREEMPT_RIGHT: u16 = 8;
+// Railroad gate controller, on GPIOC. It holds the input high while the
+// crossing is clear, so a train and a broken wire both read low.
+pub const RAIL_GATE: u16 = 6;
 
 // State definitions
 pub const ON: bool = true;
 pub const OFF: bool = false;
 
-// Traffic intensity levels
-pub const NORMAL: u8 = 0;
-pub const INTENSE: u8 = 1;
-pub const HIGH_INTENSE: u8 = 2;
-
-// Blink rates
-pub const BLINK_OFF: u8 = 0;
-pub const BLINK_SLOW: u8 = 1;
-pub const BLINK_MEDIUM: u8 = 2;
-pub const BLINK_FAST: u8 = 3;
-
 // Timing constants
 pub const TESTING_FACTOR: u16 = 5;
-pub const DEBOUNCE_DELAY_MS: u32 = 500;
\ No newline at end of file
+pub const DEBOUNCE_DELAY_MS: u32 = 500;
+// A loop detector's output is clean but cars can follow closely, so its
+// pulses only need the contact bounce filtered out.
+pub const DETECTOR_DEBOUNCE_MS: u32 = 30;
+// Detectors are sampled on the 10 Hz blink tick, so 20 samples make a 2 s
+// bin and the sliding window spans a minute.
+pub const DETECTOR_SAMPLES_PER_BIN: u16 = 20;
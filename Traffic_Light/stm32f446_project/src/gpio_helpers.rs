@@ -1,4 +1,12 @@
+use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
 use stm32f4::stm32f446::GPIOA;
+use traffic_core::LampOutput;
+
+/// GPIOA pins whose lamps the blink timer is flashing.
+pub static FLASHING_LAMPS: AtomicU16 = AtomicU16::new(0);
+
+/// Set once the conflict monitor has taken over the lamps.
+pub static LAMPS_LOCKED: AtomicBool = AtomicBool::new(false);
 
 pub fn gpio_write_pin(gpio: &GPIOA, pin: u16, state: bool) {
     match state {
@@ -18,4 +26,53 @@ pub fn gpio_init(gpio: &GPIOA, pin: u16, mode: u8) {
         bits |= (mode as u32) << (pin * 2);
         w.bits(bits)
     });
-}
\ No newline at end of file
+}
+
+pub fn gpio_pull(gpio: &GPIOA, pin: u16, pull: u8) {
+    gpio.pupdr.modify(|r, w| unsafe {
+        let mut bits = r.bits();
+        bits &= !(0b11 << (pin * 2));
+        bits |= (pull as u32) << (pin * 2);
+        w.bits(bits)
+    });
+}
+
+fn set_lamp(gpio: &GPIOA, lamp: u16, on: bool) {
+    FLASHING_LAMPS.fetch_and(!(1 << lamp), Ordering::Relaxed);
+    gpio_write_pin(gpio, lamp, on);
+}
+
+fn flash_lamp(lamp: u16) {
+    FLASHING_LAMPS.fetch_or(1 << lamp, Ordering::Relaxed);
+}
+
+/// Lamps wired to GPIOA, addressed by pin number. Writes are dropped once
+/// the conflict monitor has locked the outputs.
+pub struct GpioLamps<'a>(pub &'a GPIOA);
+
+impl LampOutput for GpioLamps<'_> {
+    fn set_lamp(&mut self, lamp: u16, on: bool) {
+        if !LAMPS_LOCKED.load(Ordering::Relaxed) {
+            set_lamp(self.0, lamp, on);
+        }
+    }
+
+    fn flash_lamp(&mut self, lamp: u16) {
+        if !LAMPS_LOCKED.load(Ordering::Relaxed) {
+            flash_lamp(lamp);
+        }
+    }
+}
+
+/// Lamp writes made by the conflict monitor, which ignore the lock.
+pub struct MonitorLamps<'a>(pub &'a GPIOA);
+
+impl LampOutput for MonitorLamps<'_> {
+    fn set_lamp(&mut self, lamp: u16, on: bool) {
+        set_lamp(self.0, lamp, on);
+    }
+
+    fn flash_lamp(&mut self, lamp: u16) {
+        flash_lamp(lamp);
+    }
+}
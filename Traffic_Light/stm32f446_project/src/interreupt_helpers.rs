@@ -1,69 +1,188 @@
+use core::cell::Cell;
 use core::sync::atomic::Ordering;
-use cortex_m::peripheral::DWT;
-use stm32f4::stm32f446::{self, EXTI, interrupt};
+use cortex_m::interrupt::{CriticalSection, Mutex};
+use stm32f4::stm32f446::{self, EXTI, GPIOA, TIM3, interrupt};
+use stm32f446_clock::{Clocks, timer_prescaler};
+use traffic_core::LampOutput;
+use traffic_core::blink::BLINK_OFF;
+use traffic_core::intensity::{NORMAL, indicator_rate};
+use traffic_core::time::{Duration, Instant};
 
 use crate::constants::*;
-use crate::gpio_helpers::gpio_write_pin;
+use crate::gpio_helpers::{FLASHING_LAMPS, LAMPS_LOCKED, MonitorLamps, gpio_write_pin};
+use crate::timer_config::now;
 use crate::traffic::{
-    LEFT_BLINK_COUNTER, LEFT_BLINK_STATE, LEFT_INDICATOR_RATE, LEFT_TRAFFIC_INTENSITY_LEVEL,
-    RIGHT_BLINK_COUNTER, RIGHT_BLINK_STATE, RIGHT_INDICATOR_RATE, RIGHT_TRAFFIC_INTENSITY_LEVEL,
+    APPROACHES, CROSSINGS, DETECTIONS, DETECTOR_THRESHOLDS, DETECTORS, FLASH_COMMAND, FLASHER,
+    INDICATOR_BLINKERS, INDICATORS, INTENSITY_LEVELS, MONITOR, PED_CALLS, PREEMPT_CALLS, PREEMPTS,
+    TRAIN_PRESENT, VEHICLE_DETECTORS, crossing_for_button, detector_for_pin, preempt_for_pin,
 };
 
-static mut LAST_EXTI4_TICK: u32 = 0;
-static mut LAST_EXTI9_5_TICK: u32 = 0;
+const DEBOUNCE: Duration = Duration::from_millis(DEBOUNCE_DELAY_MS as u64);
+const DETECTOR_DEBOUNCE: Duration = Duration::from_millis(DETECTOR_DEBOUNCE_MS as u64);
 
-#[interrupt]
-fn EXTI4() {
-    unsafe {
-        let now = DWT::cycle_count();
-        if now.wrapping_sub(LAST_EXTI4_TICK) > 16_000_000 / 1000 * DEBOUNCE_DELAY_MS {
-            LAST_EXTI4_TICK = now;
-
-            let current_level = LEFT_TRAFFIC_INTENSITY_LEVEL.load(Ordering::Relaxed);
-            let new_level = (current_level + 1) % 3;
-            LEFT_TRAFFIC_INTENSITY_LEVEL.store(new_level, Ordering::Relaxed);
-
-            match new_level {
-                NORMAL => LEFT_INDICATOR_RATE.store(BLINK_OFF, Ordering::Relaxed),
-                INTENSE => LEFT_INDICATOR_RATE.store(BLINK_MEDIUM, Ordering::Relaxed),
-                HIGH_INTENSE => LEFT_INDICATOR_RATE.store(BLINK_FAST, Ordering::Relaxed),
-                _ => LEFT_INDICATOR_RATE.store(BLINK_OFF, Ordering::Relaxed),
-            }
+type EdgeTime = Mutex<Cell<Instant>>;
 
-            if new_level == NORMAL {
-                let dp = stm32f446::Peripherals::steal();
-                gpio_write_pin(&dp.GPIOA, LEFT_TRAFFIC_INDICATOR, OFF);
-            }
+static LAST_PULSES: [EdgeTime; DETECTORS.len()] =
+    [const { Mutex::new(Cell::new(Instant::ZERO)) }; DETECTORS.len()];
+static LAST_FLASH_PRESS: EdgeTime = Mutex::new(Cell::new(Instant::ZERO));
+static LAST_PREEMPT_EDGES: [EdgeTime; PREEMPTS.len()] =
+    [const { Mutex::new(Cell::new(Instant::ZERO)) }; PREEMPTS.len()];
+static LAST_GATE_EDGE: EdgeTime = Mutex::new(Cell::new(Instant::ZERO));
+
+// SYSCFG EXTICR port codes
+pub const EXTI_PORT_A: u32 = 0;
+pub const EXTI_PORT_C: u32 = 2;
+
+/// Routes `pin` of `port` to its EXTI line, triggering on the rising edge.
+pub fn configure_exti(dp: &stm32f446::Peripherals, port: u32, pin: u16) {
+    let syscfg = &dp.SYSCFG;
+    let exti = &dp.EXTI;
+
+    let shift = (pin % 4) * 4;
+    let route = |bits: u32| (bits & !(0xF << shift)) | (port << shift);
+    match pin / 4 {
+        0 => syscfg
+            .exticr1
+            .modify(|r, w| unsafe { w.bits(route(r.bits())) }),
+        1 => syscfg
+            .exticr2
+            .modify(|r, w| unsafe { w.bits(route(r.bits())) }),
+        2 => syscfg
+            .exticr3
+            .modify(|r, w| unsafe { w.bits(route(r.bits())) }),
+        _ => syscfg
+            .exticr4
+            .modify(|r, w| unsafe { w.bits(route(r.bits())) }),
+    }
+    exti.rtsr
+        .modify(|r, w| unsafe { w.bits(r.bits() | (1 << pin)) });
+    exti.imr
+        .modify(|r, w| unsafe { w.bits(r.bits() | (1 << pin)) });
+}
+
+/// Also triggers the EXTI line of `pin` on the falling edge.
+pub fn trigger_on_falling(dp: &stm32f446::Peripherals, pin: u16) {
+    dp.EXTI
+        .ftsr
+        .modify(|r, w| unsafe { w.bits(r.bits() | (1 << pin)) });
+}
+
+/// NVIC interrupt that serves an EXTI line.
+pub fn exti_interrupt(pin: u16) -> interrupt {
+    match pin {
+        0 => interrupt::EXTI0,
+        1 => interrupt::EXTI1,
+        2 => interrupt::EXTI2,
+        3 => interrupt::EXTI3,
+        4 => interrupt::EXTI4,
+        5..=9 => interrupt::EXTI9_5,
+        _ => interrupt::EXTI15_10,
+    }
+}
+
+/// Moves an approach to `new_level` and updates its indicator.
+fn set_intensity(cs: &CriticalSection, gpioa: &GPIOA, approach: usize, new_level: u8) {
+    let level = &INTENSITY_LEVELS[approach];
+    if level.swap(new_level, Ordering::Relaxed) == new_level {
+        return;
+    }
+
+    let rate = indicator_rate(new_level);
+    INDICATOR_BLINKERS.borrow(cs).borrow_mut()[approach].set_rate(rate);
+    if rate == BLINK_OFF {
+        gpio_write_pin(gpioa, INDICATORS[approach], OFF);
+    }
+}
+
+/// Whether `debounce` has passed since the edge at `last_edge`.
+fn settled(cs: &CriticalSection, last_edge: &EdgeTime, debounce: Duration) -> bool {
+    now() - last_edge.borrow(cs).get() > debounce
+}
+
+/// Records an edge at the current time.
+fn note_edge(cs: &CriticalSection, last_edge: &EdgeTime) {
+    last_edge.borrow(cs).set(now());
+}
+
+/// Whether `debounce` has passed since `last_press`, so another edge can
+/// be accepted, recording it if so.
+fn debounced(cs: &CriticalSection, last_press: &EdgeTime, debounce: Duration) -> bool {
+    let accept = settled(cs, last_press, debounce);
+    if accept {
+        note_edge(cs, last_press);
+    }
+    accept
+}
+
+/// Serves every pending line in `first..=last`. Detector pulses and the
+/// flash button are debounced, the detectors only briefly; pedestrian calls just latch. Preemption and
+/// rail gate edges are only noted here; the blink tick takes the input's
+/// level once it has settled.
+fn handle_exti(first: u16, last: u16) {
+    let exti = unsafe { &*EXTI::ptr() };
+    let pending = exti.pr.read().bits();
+
+    for pin in first..=last {
+        if pending & (1 << pin) == 0 {
+            continue;
         }
-        (*EXTI::ptr()).pr.modify(|_, w| w.pr4().set_bit());
+        cortex_m::interrupt::free(|cs| {
+            if let Some(detector) = detector_for_pin(pin)
+                && debounced(cs, &LAST_PULSES[detector], DETECTOR_DEBOUNCE)
+            {
+                VEHICLE_DETECTORS.borrow(cs).borrow_mut()[detector].pulse();
+                DETECTIONS.fetch_or(1 << DETECTORS[detector].approach, Ordering::Relaxed);
+            }
+            if let Some(crossing) = crossing_for_button(pin) {
+                PED_CALLS.fetch_or(1 << crossing, Ordering::Relaxed);
+            }
+            if pin == FLASH_BUTTON && debounced(cs, &LAST_FLASH_PRESS, DEBOUNCE) {
+                FLASH_COMMAND.store(true, Ordering::Relaxed);
+            }
+            if let Some(input) = preempt_for_pin(pin) {
+                note_edge(cs, &LAST_PREEMPT_EDGES[input]);
+            }
+            if pin == RAIL_GATE {
+                note_edge(cs, &LAST_GATE_EDGE);
+            }
+        });
+        exti.pr.write(|w| unsafe { w.bits(1 << pin) });
     }
 }
 
+#[interrupt]
+fn EXTI0() {
+    handle_exti(0, 0);
+}
+
+#[interrupt]
+fn EXTI1() {
+    handle_exti(1, 1);
+}
+
+#[interrupt]
+fn EXTI2() {
+    handle_exti(2, 2);
+}
+
+#[interrupt]
+fn EXTI3() {
+    handle_exti(3, 3);
+}
+
+#[interrupt]
+fn EXTI4() {
+    handle_exti(4, 4);
+}
+
 #[interrupt]
 fn EXTI9_5() {
-    unsafe {
-        let now = DWT::cycle_count();
-        if now.wrapping_sub(LAST_EXTI9_5_TICK) > 16_000_000 / 1000 * DEBOUNCE_DELAY_MS {
-            LAST_EXTI9_5_TICK = now;
-
-            let current_level = RIGHT_TRAFFIC_INTENSITY_LEVEL.load(Ordering::Relaxed);
-            let new_level = (current_level + 1) % 3;
-            RIGHT_TRAFFIC_INTENSITY_LEVEL.store(new_level, Ordering::Relaxed);
-
-            match new_level {
-                NORMAL => RIGHT_INDICATOR_RATE.store(BLINK_OFF, Ordering::Relaxed),
-                INTENSE => RIGHT_INDICATOR_RATE.store(BLINK_MEDIUM, Ordering::Relaxed),
-                HIGH_INTENSE => RIGHT_INDICATOR_RATE.store(BLINK_FAST, Ordering::Relaxed),
-                _ => RIGHT_INDICATOR_RATE.store(BLINK_OFF, Ordering::Relaxed),
-            }
+    handle_exti(5, 9);
+}
 
-            if new_level == NORMAL {
-                let dp = stm32f446::Peripherals::steal();
-                gpio_write_pin(&dp.GPIOA, RIGHT_TRAFFIC_INDICATOR, OFF);
-            }
-        }
-        (*EXTI::ptr()).pr.modify(|_, w| w.pr7().set_bit());
-    }
+#[interrupt]
+fn EXTI15_10() {
+    handle_exti(10, 15);
 }
 
 #[interrupt]
@@ -72,91 +191,94 @@ fn TIM3() {
         let dp = stm32f446::Peripherals::steal();
         let gpioa = &dp.GPIOA;
 
-        let left_rate = LEFT_INDICATOR_RATE.load(Ordering::Relaxed);
-        let right_rate = RIGHT_INDICATOR_RATE.load(Ordering::Relaxed);
+        let time = now();
+        cortex_m::interrupt::free(|cs| {
+            // Check the lamps as actually driven, counting flashing lamps
+            // as lit through their dark half, and keep holding them in
+            // flashing red for as long as the fault is latched.
+            let lit = gpioa.odr.read().bits() | FLASHING_LAMPS.load(Ordering::Relaxed) as u32;
+            let mut monitor = MONITOR.borrow(cs).borrow_mut();
+            if monitor.check(lit).is_some() {
+                LAMPS_LOCKED.store(true, Ordering::Relaxed);
 
-        if left_rate > BLINK_OFF {
-            let left_counter = LEFT_BLINK_COUNTER.load(Ordering::Relaxed);
-            let mut update_left = false;
-
-            match left_rate {
-                BLINK_FAST => {
-                    if left_counter % 3 == 0 {
-                        update_left = true;
-                    }
+                let mut lamps = MonitorLamps(gpioa);
+                monitor.fail_safe(&mut lamps);
+                for crossing in CROSSINGS.iter() {
+                    lamps.set_lamp(crossing.head.walk, OFF);
+                    lamps.set_lamp(crossing.head.dont_walk, ON);
                 }
-                BLINK_MEDIUM => {
-                    if left_counter % 8 == 0 {
-                        update_left = true;
-                    }
+            }
+
+            let preempt_inputs = dp.GPIOC.idr.read().bits();
+            for (input, config) in PREEMPTS.iter().enumerate() {
+                if !settled(cs, &LAST_PREEMPT_EDGES[input], DEBOUNCE) {
+                    continue;
                 }
-                BLINK_SLOW => {
-                    if left_counter % 6 == 0 {
-                        update_left = true;
-                    }
+                if preempt_inputs & (1 << config.pin) != 0 {
+                    PREEMPT_CALLS.fetch_or(1 << input, Ordering::Relaxed);
+                } else {
+                    PREEMPT_CALLS.fetch_and(!(1 << input), Ordering::Relaxed);
                 }
-                _ => {}
-            }   
-
-            if update_left {
-                let current_left_state = LEFT_BLINK_STATE.load(Ordering::Relaxed);
-                let new_left_state = !current_left_state;
-                LEFT_BLINK_STATE.store(new_left_state, Ordering::Relaxed);
-                gpio_write_pin(
-                    gpioa,
-                    LEFT_TRAFFIC_INDICATOR,
-                    if new_left_state { ON } else { OFF },
-                );
+            }
+            // A low gate input counts as a train straight away; only the
+            // all-clear has to settle.
+            if preempt_inputs & (1 << RAIL_GATE) == 0 {
+                TRAIN_PRESENT.store(true, Ordering::Relaxed);
+            } else if settled(cs, &LAST_GATE_EDGE, DEBOUNCE) {
+                TRAIN_PRESENT.store(false, Ordering::Relaxed);
             }
 
-            LEFT_BLINK_COUNTER.store((left_counter + 1) % 12, Ordering::Relaxed);
-        }
-
-        if right_rate > BLINK_OFF {
-            let right_counter = RIGHT_BLINK_COUNTER.load(Ordering::Relaxed);
-            let mut update_right = false;
+            // Sample every detector for occupancy, and let the busiest
+            // detector of each approach set its intensity.
+            let inputs = gpioa.idr.read().bits();
+            let mut detectors = VEHICLE_DETECTORS.borrow(cs).borrow_mut();
+            for (detector, input) in detectors.iter_mut().zip(DETECTORS.iter()) {
+                detector.sample(inputs & (1 << input.pin) != 0);
+            }
+            for approach in 0..APPROACHES.len() {
+                let level = detectors
+                    .iter()
+                    .zip(DETECTORS.iter())
+                    .filter(|(_, input)| input.approach == approach)
+                    .map(|(detector, _)| detector.level(&DETECTOR_THRESHOLDS))
+                    .max()
+                    .unwrap_or(NORMAL);
+                set_intensity(cs, gpioa, approach, level);
+            }
 
-            match right_rate {
-                BLINK_FAST => {
-                    if right_counter % 3 == 0 {
-                        update_right = true;
-                    }
-                }
-                BLINK_MEDIUM => {
-                    if right_counter % 8 == 0 {
-                        update_right = true;
-                    }
+            let mut blinkers = INDICATOR_BLINKERS.borrow(cs).borrow_mut();
+            for (blinker, &indicator) in blinkers.iter_mut().zip(INDICATORS.iter()) {
+                if let Some(state) = blinker.update(time) {
+                    gpio_write_pin(gpioa, indicator, if state { ON } else { OFF });
                 }
-                BLINK_SLOW => {
-                    if right_counter % 6 == 0 {
-                        update_right = true;
-                    }
-                }
-                _ => {}
             }
 
-            if update_right {
-                let current_right_state = RIGHT_BLINK_STATE.load(Ordering::Relaxed);
-                let new_right_state = !current_right_state;
-                RIGHT_BLINK_STATE.store(new_right_state, Ordering::Relaxed);
-                gpio_write_pin(
-                    gpioa,
-                    RIGHT_TRAFFIC_INDICATOR,
-                    if new_right_state { ON } else { OFF },
-                );
+            if let Some(state) = FLASHER.borrow(cs).borrow_mut().update(time) {
+                let flashing = FLASHING_LAMPS.load(Ordering::Relaxed);
+                for pin in (0..16).filter(|pin| flashing & (1 << pin) != 0) {
+                    gpio_write_pin(gpioa, pin, state);
+                }
             }
-
-            RIGHT_BLINK_COUNTER.store((right_counter + 1) % 12, Ordering::Relaxed);
-        }
+        });
 
         dp.TIM3.sr.modify(|_, w| w.uif().clear_bit());
     }
 }
 
-pub fn configure_blink_timer(dp: &stm32f446::Peripherals) {
+pub fn configure_blink_timer(dp: &stm32f446::Peripherals, clocks: &Clocks) {
     dp.RCC.apb1enr.modify(|_, w| w.tim3en().set_bit());
-    dp.TIM3.psc.write(|w| w.psc().bits(15999));
-    dp.TIM3.arr.write(|w| w.arr().bits(100));
+    // Count at 10 kHz and update every 1000 counts, a 10 Hz tick.
+    let psc = timer_prescaler(clocks.timclk1(), 10_000);
+    dp.TIM3.psc.write(|w| w.psc().bits(psc as u16));
+    dp.TIM3.arr.write(|w| w.arr().bits(1000 - 1));
     dp.TIM3.dier.write(|w| w.uie().set_bit());
     dp.TIM3.cr1.modify(|_, w| w.cen().set_bit());
 }
+
+/// Keeps the blink tick at 10 Hz across a clock switch. The new prescaler
+/// takes over at the next tick.
+pub fn retime_blink_timer(clocks: &Clocks) {
+    let tim3 = unsafe { &*TIM3::ptr() };
+    let psc = timer_prescaler(clocks.timclk1(), 10_000);
+    tim3.psc.write(|w| w.psc().bits(psc as u16));
+}
@@ -9,100 +9,271 @@ mod constants;
 mod gpio_helpers;
 mod interreupt_helpers;
 mod rcc_config;
+mod rtc;
 mod timer_config;
 mod traffic;
 
-use cortex_m::peripheral::Peripherals as CorePeripherals;
-use rcc_config::configure_system_clock;
+use rcc_config::{
+    FULL_SPEED, HSE_FAILED, HSI_FALLBACKS, LOW_POWER, register_clock_user, switch_system_clock,
+    take_pending_fallback,
+};
+use rtc::{configure_rtc, rtc_time};
 use stm32f4::stm32f446::{self, NVIC, Peripherals, interrupt};
-use timer_config::{configure_timer, delay_s};
+use timer_config::{SECOND, configure_timer, now, retime_timer, set_alarm};
 
-use constants::*;
-use gpio_helpers::{gpio_init, gpio_write_pin};
-use interreupt_helpers::configure_blink_timer;
-use traffic::{LEFT_TRAFFIC_INTENSITY_LEVEL, RIGHT_TRAFFIC_INTENSITY_LEVEL, get_traffic_delays};
+use constants::{FLASH_BUTTON, RAIL_GATE};
+use gpio_helpers::{GpioLamps, LAMPS_LOCKED, gpio_init, gpio_pull};
+use interreupt_helpers::{
+    EXTI_PORT_A, EXTI_PORT_C, configure_blink_timer, configure_exti, exti_interrupt,
+    retime_blink_timer, trigger_on_falling,
+};
+use stm32f446_clock::ClockSource;
+use traffic::{
+    APPROACHES, CROSSINGS, DETECTIONS, DETECTORS, EVENT_LOG, FLASH_COMMAND, INDICATORS,
+    INTERSECTION, PED_CALLS, PREEMPT_CALLS, PREEMPTS, RAIL, RTC_START_TIME, SCHEDULE,
+    TRAIN_PRESENT, intensity_levels,
+};
+use traffic_core::Controller;
+use traffic_core::controller::{FlashCause, PreemptStage};
+use traffic_core::events::Event;
+use traffic_core::timing::{STANDARD_PLAN, TimingPlan};
+
+fn log_event(event: Event) {
+    let time = rtc_time().time;
+    cortex_m::interrupt::free(|cs| EVENT_LOG.borrow(cs).borrow_mut().record(time, event));
+}
+
+/// Plan due now, and whether the schedule has the site flashing.
+fn scheduled() -> (TimingPlan, bool) {
+    SCHEDULE
+        .entry_at(rtc_time())
+        .map_or((STANDARD_PLAN, false), |entry| (*entry.plan, entry.flash))
+}
 
 #[entry]
 fn main() -> ! {
-    configure_system_clock();
-    configure_timer();
-
-    let mut cp = CorePeripherals::take().unwrap();
-    cp.DWT.enable_cycle_counter();
+    let clock_plan = switch_system_clock(&FULL_SPEED);
+    let clocks = clock_plan.clocks;
+    configure_timer(&clocks);
+    register_clock_user(retime_timer);
+    let lse = configure_rtc(RTC_START_TIME);
+    if let ClockSource::Hsi = clock_plan.source {
+        log_event(Event::HseFailed);
+    }
+    if !lse {
+        log_event(Event::LseFailed);
+    }
 
     let dp: Peripherals = unsafe { stm32f446::Peripherals::steal() };
 
-    dp.RCC.ahb1enr.modify(|_, w| w.gpioaen().set_bit());
+    dp.RCC
+        .ahb1enr
+        .modify(|_, w| w.gpioaen().set_bit().gpiocen().set_bit());
     dp.RCC.apb2enr.modify(|_, w| w.syscfgen().set_bit());
 
-    dp.SYSCFG
-        .exticr2
-        .modify(|_, w| unsafe { w.exti4().bits(0b0000) });
-    dp.EXTI.rtsr.modify(|_, w| w.tr4().set_bit());
-    dp.EXTI.imr.modify(|_, w| w.mr4().set_bit());
+    configure_blink_timer(&dp, &clocks);
+    register_clock_user(retime_blink_timer);
 
-    dp.SYSCFG
-        .exticr2
-        .modify(|_, w| unsafe { w.exti7().bits(0b0000) });
-    dp.EXTI.rtsr.modify(|_, w| w.tr7().set_bit());
-    dp.EXTI.imr.modify(|_, w| w.mr7().set_bit());
+    for approach in APPROACHES.iter() {
+        for head in approach.heads() {
+            gpio_init(&dp.GPIOA, head.red, 0b01);
+            gpio_init(&dp.GPIOA, head.yellow, 0b01);
+            gpio_init(&dp.GPIOA, head.green, 0b01);
+        }
+    }
+    for detector in DETECTORS.iter() {
+        gpio_init(&dp.GPIOA, detector.pin, 0b00);
+        gpio_pull(&dp.GPIOA, detector.pin, 0b10);
+        configure_exti(&dp, EXTI_PORT_A, detector.pin);
+        unsafe {
+            NVIC::unmask(exti_interrupt(detector.pin));
+        }
+    }
+    for &indicator in INDICATORS.iter() {
+        gpio_init(&dp.GPIOA, indicator, 0b01);
+    }
+    for crossing in CROSSINGS.iter() {
+        gpio_init(&dp.GPIOA, crossing.head.walk, 0b01);
+        gpio_init(&dp.GPIOA, crossing.head.dont_walk, 0b01);
 
-    configure_blink_timer(&dp);
+        gpio_init(&dp.GPIOA, crossing.button, 0b00);
+        gpio_pull(&dp.GPIOA, crossing.button, 0b10);
+        configure_exti(&dp, EXTI_PORT_A, crossing.button);
+        unsafe {
+            NVIC::unmask(exti_interrupt(crossing.button));
+        }
+    }
+    // Preemption receivers drive their input high while a vehicle calls;
+    // both edges are watched so the call clears too.
+    for input in PREEMPTS.iter() {
+        let shift = input.pin * 2;
+        dp.GPIOC
+            .pupdr
+            .modify(|r, w| unsafe { w.bits((r.bits() & !(0b11 << shift)) | (0b10 << shift)) });
+        configure_exti(&dp, EXTI_PORT_C, input.pin);
+        trigger_on_falling(&dp, input.pin);
+        unsafe {
+            NVIC::unmask(exti_interrupt(input.pin));
+        }
+    }
+    // The pull-down makes a disconnected gate input read as a train.
+    let shift = RAIL_GATE * 2;
+    dp.GPIOC
+        .pupdr
+        .modify(|r, w| unsafe { w.bits((r.bits() & !(0b11 << shift)) | (0b10 << shift)) });
+    configure_exti(&dp, EXTI_PORT_C, RAIL_GATE);
+    trigger_on_falling(&dp, RAIL_GATE);
+    unsafe {
+        NVIC::unmask(exti_interrupt(RAIL_GATE));
+    }
+    // B1 has its own pull-up and reads low while held, so the rising edge
+    // fires when it is released.
+    configure_exti(&dp, EXTI_PORT_C, FLASH_BUTTON);
+    unsafe {
+        NVIC::unmask(exti_interrupt(FLASH_BUTTON));
+    }
 
+    let (plan, mut scheduled_flash) = scheduled();
+    let mut controller = Controller::new(&INTERSECTION, plan);
+    if scheduled_flash {
+        controller.enter_flash(FlashCause::Schedule);
+    }
+    let mut lamps = GpioLamps(&dp.GPIOA);
+    let wait = controller.update(0, &intensity_levels(), &mut lamps);
+    let mut last_update = now();
+    let mut deadline = last_update + SECOND * u64::from(wait.max(1));
+    let mut preempt_calls = 0;
+    let mut preemption = None;
+    let mut train_present = false;
+    let mut rail_preemption = None;
+    let mut low_power = false;
+
+    // The conflict monitor runs on the TIM3 tick, so only start it once the
+    // lamps show a valid state.
     unsafe {
-        NVIC::unmask(interrupt::EXTI4);
-        NVIC::unmask(interrupt::EXTI9_5);
         NVIC::unmask(interrupt::TIM3);
     }
 
-    gpio_init(&dp.GPIOA, RED_LEFT, 0b01);
-    gpio_init(&dp.GPIOA, YELLOW_LEFT, 0b01);
-    gpio_init(&dp.GPIOA, GREEN_LEFT, 0b01);
-    gpio_init(&dp.GPIOA, GREEN_RIGHT, 0b01);
-    gpio_init(&dp.GPIOA, YELLOW_RIGHT, 0b01);
-    gpio_init(&dp.GPIOA, RED_RIGHT, 0b01);
-    gpio_init(&dp.GPIOA, LEFT_TRAFFIC_INTENSITY, 0b00);
-    gpio_init(&dp.GPIOA, RIGHT_TRAFFIC_INSTENSITY, 0b00);
-    // gpio_init(&dp.GPIOA, 5, 0b01);
-    gpio_init(&dp.GPIOA, LEFT_TRAFFIC_INDICATOR, 0b01);
-    gpio_init(&dp.GPIOA, RIGHT_TRAFFIC_INDICATOR, 0b01);
-
-    dp.GPIOA.pupdr.modify(|_, w| w.pupdr4().pull_down());
-    dp.GPIOA.pupdr.modify(|_, w| w.pupdr7().pull_down());
-
     loop {
-        let left_intensity = LEFT_TRAFFIC_INTENSITY_LEVEL.load(Ordering::Relaxed);
-        let right_intensity = RIGHT_TRAFFIC_INTENSITY_LEVEL.load(Ordering::Relaxed);
-
-        let mut delay = get_traffic_delays(left_intensity, right_intensity);
-        //right green, left red
-        gpio_write_pin(&dp.GPIOA, RED_LEFT, ON);
-        gpio_write_pin(&dp.GPIOA, GREEN_RIGHT, ON);
-
-        delay_s(delay[0] / TESTING_FACTOR);
-        // yellow
-        gpio_write_pin(&dp.GPIOA, GREEN_RIGHT, OFF);
-        gpio_write_pin(&dp.GPIOA, YELLOW_RIGHT, ON);
-
-        delay_s(delay[1] / TESTING_FACTOR);
-
-        let left_intensity = LEFT_TRAFFIC_INTENSITY_LEVEL.load(Ordering::Relaxed);
-        let right_intensity = RIGHT_TRAFFIC_INTENSITY_LEVEL.load(Ordering::Relaxed);
-
-        delay = get_traffic_delays(left_intensity, right_intensity);
-        
-        //left green, right red
-        gpio_write_pin(&dp.GPIOA, RED_LEFT, OFF);
-        gpio_write_pin(&dp.GPIOA, YELLOW_RIGHT, OFF);
-        gpio_write_pin(&dp.GPIOA, GREEN_LEFT, ON);
-        gpio_write_pin(&dp.GPIOA, RED_RIGHT, ON);
-        delay_s(delay[2] / TESTING_FACTOR);
-
-        //yellow
-        gpio_write_pin(&dp.GPIOA, GREEN_LEFT, OFF);
-        gpio_write_pin(&dp.GPIOA, YELLOW_LEFT, ON);
-        delay_s(delay[3] / TESTING_FACTOR);
-        gpio_write_pin(&dp.GPIOA, YELLOW_LEFT, OFF);
-        gpio_write_pin(&dp.GPIOA, RED_RIGHT, OFF);
+        // Sleep until the next deadline. Any other interrupt wakes the loop
+        // too, so the controller hears about inputs as soon as they arrive.
+        // With interrupts masked, an alarm firing between the check and
+        // WFI stays pending and WFI returns at once, instead of the handler
+        // running first and WFI sleeping on to the next tick.
+        set_alarm(deadline);
+        cortex_m::interrupt::free(|_| {
+            if now() < deadline {
+                cortex_m::asm::wfi();
+            }
+        });
+        let time = now();
+        if HSE_FAILED.swap(false, Ordering::Relaxed) {
+            if take_pending_fallback() {
+                switch_system_clock(if low_power {
+                    &LOW_POWER
+                } else {
+                    &HSI_FALLBACKS
+                });
+            }
+            log_event(Event::HseFailed);
+        }
+        let inputs = PED_CALLS.load(Ordering::Relaxed) != 0
+            || DETECTIONS.load(Ordering::Relaxed) != 0
+            || FLASH_COMMAND.load(Ordering::Relaxed)
+            || PREEMPT_CALLS.load(Ordering::Relaxed) != preempt_calls
+            || TRAIN_PRESENT.load(Ordering::Relaxed) != train_present
+            || (LAMPS_LOCKED.load(Ordering::Relaxed) && controller.flashing().is_none());
+        if !inputs && time < deadline {
+            continue;
+        }
+
+        controller.call_pedestrians(PED_CALLS.swap(0, Ordering::Relaxed));
+        controller.detect(DETECTIONS.swap(0, Ordering::Relaxed));
+
+        let (plan, flash) = scheduled();
+        controller.set_plan(plan);
+        // Act on the schedule only where it changes, so a command can
+        // override it in between.
+        if flash != scheduled_flash {
+            scheduled_flash = flash;
+            if flash {
+                controller.enter_flash(FlashCause::Schedule);
+            } else if controller.flashing() == Some(FlashCause::Schedule) {
+                controller.leave_flash();
+            }
+        }
+        if FLASH_COMMAND.swap(false, Ordering::Relaxed) {
+            match controller.flashing() {
+                None => controller.enter_flash(FlashCause::Command),
+                Some(FlashCause::Fault) => {}
+                Some(_) => controller.leave_flash(),
+            }
+        }
+        if LAMPS_LOCKED.load(Ordering::Relaxed) {
+            controller.enter_flash(FlashCause::Fault);
+        }
+        // Nothing needs the full clock through the night flash.
+        let night = controller.flashing() == Some(FlashCause::Schedule);
+        if night != low_power {
+            low_power = night;
+            let plan = switch_system_clock(if night { &LOW_POWER } else { &FULL_SPEED });
+            if !night && plan.source == ClockSource::Hsi {
+                log_event(Event::HseFailed);
+            }
+        }
+
+        let calls = PREEMPT_CALLS.load(Ordering::Relaxed);
+        for (input, config) in PREEMPTS.iter().enumerate() {
+            if calls & (1 << input) != 0 {
+                controller.preempt(config.approach, config.reentry);
+            } else {
+                controller.clear_preempt(config.approach);
+            }
+        }
+        preempt_calls = calls;
+
+        let train = TRAIN_PRESENT.load(Ordering::Relaxed);
+        if train {
+            if !train_present {
+                log_event(Event::RailPreemptCalled);
+            }
+            controller.rail_preempt(&RAIL);
+        } else {
+            controller.clear_rail_preempt();
+        }
+        train_present = train;
+
+        // Only whole controller seconds are accounted; the rest carries
+        // over to the next update.
+        let seconds = (time - last_update).whole(SECOND);
+        last_update += SECOND * seconds;
+        let wait = controller.update(seconds as u16, &intensity_levels(), &mut lamps);
+        // With nothing being timed, look again every second for the
+        // schedule.
+        deadline = last_update + SECOND * u64::from(wait.max(1));
+
+        // Calls are logged once the controller takes them up, not when the
+        // input goes high, so ignored calls stay out of the log.
+        let now = controller.preemption();
+        if now != preemption {
+            if let Some((approach, _)) = now
+                && preemption.map(|(previous, _)| previous) != Some(approach)
+            {
+                log_event(Event::PreemptCalled(approach));
+            }
+            match (preemption, now) {
+                (_, Some((approach, PreemptStage::Dwell))) => {
+                    log_event(Event::PreemptDwell(approach))
+                }
+                (Some((approach, _)), None) => log_event(Event::PreemptEnded(approach)),
+                _ => {}
+            }
+            preemption = now;
+        }
+        let now = controller.rail_preemption();
+        if rail_preemption.is_some() && now.is_none() {
+            log_event(Event::RailPreemptEnded);
+        }
+        rail_preemption = now;
     }
 }
@@ -1,43 +1,320 @@
-use stm32f4::stm32f446::{self, Peripherals};
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::exception;
+use stm32f4::stm32f446::{self, PWR, Peripherals};
+use stm32f446_clock::{
+    Clock48Source, ClockError, ClockPlan, ClockSource, ClockUsers, Clocks, RccConfig,
+};
 
-pub fn configure_system_clock() {
+/// Crystal on the board's HSE input, from the ST-LINK MCO.
+const HSE_HZ: u32 = 8_000_000;
+
+/// The core at 180 MHz from the HSE, APB1 and APB2 as fast as they may go.
+pub const CLOCK_PLAN: ClockPlan = match RccConfig::new()
+    .use_hse(HSE_HZ)
+    .sysclk(180_000_000)
+    .freeze()
+{
+    Ok(plan) => plan,
+    Err(_) => panic!("clock configuration cannot be met"),
+};
+
+/// `CLOCK_PLAN`'s clocks from the HSI, so the timers keep time if the HSE
+/// fails.
+pub const FALLBACK_PLAN: ClockPlan = match CLOCK_PLAN.on_hsi() {
+    Ok(plan) => plan,
+    Err(_) => panic!("clock configuration cannot be met"),
+};
+
+/// The HSI alone, which needs nothing to start and cannot fail.
+const HSI_PLAN: ClockPlan = match RccConfig::new().freeze() {
+    Ok(plan) => plan,
+    Err(_) => panic!("clock configuration cannot be met"),
+};
+
+/// The fastest the core goes without over-drive, for a regulator that does
+/// not enter it.
+const NO_OVERDRIVE_PLAN: ClockPlan = match RccConfig::new()
+    .use_hse(HSE_HZ)
+    .sysclk(168_000_000)
+    .freeze()
+{
+    Ok(plan) => plan,
+    Err(_) => panic!("clock configuration cannot be met"),
+};
+
+/// `NO_OVERDRIVE_PLAN`'s clocks from the HSI.
+const NO_OVERDRIVE_FALLBACK_PLAN: ClockPlan = match NO_OVERDRIVE_PLAN.on_hsi() {
+    Ok(plan) => plan,
+    Err(_) => panic!("clock configuration cannot be met"),
+};
+
+/// Plans to try for full speed, best first.
+pub const FULL_SPEED: [ClockPlan; 5] = [
+    CLOCK_PLAN,
+    NO_OVERDRIVE_PLAN,
+    FALLBACK_PLAN,
+    NO_OVERDRIVE_FALLBACK_PLAN,
+    HSI_PLAN,
+];
+
+/// Plans to try once the HSE has failed, best first.
+pub const HSI_FALLBACKS: [ClockPlan; 3] = [FALLBACK_PLAN, NO_OVERDRIVE_FALLBACK_PLAN, HSI_PLAN];
+
+/// The HSI alone with the HSE and every PLL stopped, for the night flash.
+pub const LOW_POWER: [ClockPlan; 1] = [HSI_PLAN];
+
+/// Polls of a ready flag before giving up: over 100 ms on the 16 MHz HSI,
+/// where the HSE and PLL need a few milliseconds at most.
+const READY_POLLS: u32 = 400_000;
+
+/// Set by the clock security system when the HSE fails while running.
+pub static HSE_FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Set while [`switch_system_clock`] is reprogramming the clocks. The NMI
+/// cannot be masked, so it checks this rather than start a second switch
+/// on top of the first.
+static SWITCHING: AtomicBool = AtomicBool::new(false);
+
+/// Set by the NMI when the HSE failed part-way through a switch, for
+/// [`take_pending_fallback`] to pick up once it is over.
+static FALLBACK_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Whether the HSE failed during a switch, leaving the move to the HSI to
+/// the caller.
+pub fn take_pending_fallback() -> bool {
+    FALLBACK_PENDING.swap(false, Ordering::SeqCst)
+}
+
+/// Peripherals that reprogram their dividers when the clocks switch.
+static CLOCK_USERS: Mutex<RefCell<ClockUsers<4>>> = Mutex::new(RefCell::new(ClockUsers::new()));
+
+/// Calls `user` with the new clocks whenever the system clock switches.
+pub fn register_clock_user(user: fn(&Clocks)) {
+    cortex_m::interrupt::free(|cs| {
+        let registered = CLOCK_USERS.borrow(cs).borrow_mut().register(user);
+        debug_assert!(registered, "too many clock users");
+    });
+}
+
+/// Polls `ready` until it holds, or gives up after `READY_POLLS`.
+fn wait_for(ready: impl Fn() -> bool) -> bool {
+    (0..READY_POLLS).any(|_| ready())
+}
+
+/// Switches the clocks to the first of `plans` that comes up, or to the
+/// bare HSI if none does, with the clock security system watching the HSE
+/// if that is what they run from. Every registered user then hears about
+/// the new clocks before interrupts come back on, so no timer runs a tick
+/// on the wrong prescaler. Returns the plan the board ended up on.
+pub fn switch_system_clock(plans: &'static [ClockPlan]) -> &'static ClockPlan {
+    cortex_m::interrupt::free(|cs| {
+        SWITCHING.store(true, Ordering::SeqCst);
+        let plan = plans
+            .iter()
+            .find(|plan| configure_system_clock(plan).is_ok())
+            .unwrap_or(&HSI_PLAN);
+        if let ClockSource::Hse(_) = plan.source {
+            let rcc = unsafe { &*stm32f446::RCC::ptr() };
+            rcc.cr.modify(|_, w| w.csson().on());
+        }
+        // Only an NMI landing in the middle of a registration finds the
+        // users borrowed.
+        if let Ok(users) = CLOCK_USERS.borrow(cs).try_borrow() {
+            users.notify(&plan.clocks);
+        }
+        SWITCHING.store(false, Ordering::SeqCst);
+        plan
+    })
+}
+
+/// Programs the clock tree as `plan` works it out and switches the system
+/// clock over. Returns the resulting clocks.
+///
+/// The system clock is parked on the 16 MHz HSI while everything else
+/// changes, which any flash latency and voltage scale support. Raising the
+/// latency before a faster clock and lowering it only after a slower one
+/// then falls out of the order, as does only entering over-drive once the
+/// PLL is up and leaving it before the PLL goes down. If the HSE or
+/// the PLL does not come up in time, or the system clock does not switch
+/// over to it, it is switched off again, leaving the system clock on the
+/// HSI.
+pub fn configure_system_clock(plan: &ClockPlan) -> Result<Clocks, ClockError> {
     let dp: Peripherals = unsafe { stm32f446::Peripherals::steal() };
     let rcc = &dp.RCC;
 
-    rcc.cr.modify(|_, w| w.hseon().on());
+    // These waits are left unbounded: the HSI is an on-chip RC oscillator
+    // with nothing outside to fail, ready within microseconds, and the
+    // switch to it and the PLLs stopping take a few cycles once it runs.
+    // Were it to fail there would be no clock to fall back to anyway.
+    rcc.cr.modify(|_, w| w.hsion().on());
+    while rcc.cr.read().hsirdy().is_not_ready() {}
+    rcc.cfgr.modify(|_, w| w.sw().hsi());
+    while !rcc.cfgr.read().sws().is_hsi() {}
+    // All three PLLs share an input, so all of them stop while it changes.
+    rcc.cr
+        .modify(|_, w| w.pllon().off().pllsaion().off().plli2son().off());
+    while rcc.cr.read().pllrdy().is_ready()
+        || rcc.cr.read().pllsairdy().is_ready()
+        || rcc.cr.read().plli2srdy().is_ready()
+    {}
+    if let ClockSource::Hsi = plan.source {
+        // Nothing left for the clock security system to watch.
+        rcc.cr.modify(|_, w| w.csson().off().hseon().off());
+    }
 
-    while rcc.cr.read().hserdy().is_not_ready() {}
+    if let ClockSource::Hse(_) = plan.source {
+        rcc.cr.modify(|_, w| w.hseon().on());
+        if !wait_for(|| rcc.cr.read().hserdy().is_ready()) {
+            rcc.cr.modify(|_, w| w.hseon().off());
+            return Err(ClockError::HseNotReady);
+        }
+    }
 
     rcc.apb1enr.modify(|_, w| w.pwren().enabled());
 
     let pwr = &dp.PWR;
-    pwr.cr.modify(|_, w| w.vos().variant(0b11));
+    if !plan.overdrive {
+        disable_overdrive(pwr);
+    }
+    // Takes effect once the PLL is on.
+    pwr.cr.modify(|_, w| w.vos().variant(plan.vos.bits()));
 
     let flash = &dp.FLASH;
     flash.acr.modify(|_, w| {
         w.icen().enabled();
         w.dcen().enabled();
         w.prften().enabled();
-        w.latency().ws5()
+        w.latency().bits(plan.flash_latency)
     });
 
-    rcc.cfgr.modify(|_r, w| {
-        w.hpre().div1();
-        w.ppre1().div4();
-        w.ppre2().div2()
+    rcc.cfgr.modify(|_r, w| unsafe {
+        w.hpre().bits(plan.hpre_bits());
+        w.ppre1().bits(plan.ppre1_bits());
+        w.ppre2().bits(plan.ppre2_bits())
     });
 
+    rcc.pllcfgr.modify(|_, w| match plan.source {
+        ClockSource::Hsi => w.pllsrc().hsi(),
+        ClockSource::Hse(_) => w.pllsrc().hse(),
+    });
+    if let Some(sai) = plan.pllsai {
+        rcc.pllsaicfgr.write(|w| unsafe {
+            w.pllsaim().bits(sai.m as u8);
+            w.pllsain().bits(sai.n as u16);
+            w.pllsaip().bits(sai.p_bits());
+            w.pllsaiq().bits(sai.q as u8)
+        });
+        rcc.cr.modify(|_, w| w.pllsaion().on());
+        if !wait_for(|| rcc.cr.read().pllsairdy().is_ready()) {
+            rcc.cr.modify(|_, w| w.pllsaion().off());
+            return Err(ClockError::PllNotReady);
+        }
+    }
+    if let Some(i2s) = plan.plli2s {
+        rcc.plli2scfgr.write(|w| unsafe {
+            w.plli2sm().bits(i2s.m as u8);
+            w.plli2sn().bits(i2s.n as u16);
+            w.plli2sp().bits(i2s.p_bits());
+            w.plli2sq().bits(i2s.q as u8);
+            w.plli2sr().bits(i2s.r as u8)
+        });
+        rcc.cr.modify(|_, w| w.plli2son().on());
+        if !wait_for(|| rcc.cr.read().plli2srdy().is_ready()) {
+            rcc.cr.modify(|_, w| w.plli2son().off());
+            return Err(ClockError::PllNotReady);
+        }
+    }
+    rcc.dckcfgr2.modify(|_, w| match plan.clock48 {
+        Some(Clock48Source::PllSaiP) => w.ck48msel().pllsai(),
+        _ => w.ck48msel().pll(),
+    });
+
+    let Some(pll) = plan.pll else {
+        if let ClockSource::Hse(_) = plan.source {
+            rcc.cfgr.modify(|_, w| w.sw().hse());
+            if !wait_for(|| rcc.cfgr.read().sws().is_hse()) {
+                rcc.cfgr.modify(|_, w| w.sw().hsi());
+                rcc.cr.modify(|_, w| w.hseon().off());
+                return Err(ClockError::HseNotReady);
+            }
+        }
+        return Ok(plan.clocks);
+    };
+
     rcc.pllcfgr.modify(|_, w| unsafe {
-        w.pllm().bits(4);
-        w.plln().bits(180);
-        w.pllp().div2();
-        w.pllsrc().hse()
+        w.pllm().bits(pll.m as u8);
+        w.plln().bits(pll.n as u16);
+        w.pllp().bits(pll.p_bits());
+        w.pllq().bits(pll.q as u8);
+        w.pllr().bits(pll.r as u8)
     });
 
     rcc.cr.modify(|_, w| w.pllon().on());
-    while rcc.cr.read().pllrdy().is_not_ready() {}
+    if !wait_for(|| rcc.cr.read().pllrdy().is_ready()) {
+        rcc.cr.modify(|_, w| w.pllon().off());
+        return Err(ClockError::PllNotReady);
+    }
+
+    if plan.overdrive && !enable_overdrive(pwr) {
+        rcc.cr.modify(|_, w| w.pllon().off());
+        disable_overdrive(pwr);
+        return Err(ClockError::OverdriveNotReady);
+    }
 
     rcc.cfgr.modify(|_, w| w.sw().pll());
+    if !wait_for(|| rcc.cfgr.read().sws().is_pll()) {
+        rcc.cfgr.modify(|_, w| w.sw().hsi());
+        rcc.cr.modify(|_, w| w.pllon().off());
+        disable_overdrive(pwr);
+        return Err(ClockError::PllNotReady);
+    }
+
+    Ok(plan.clocks)
+}
+
+/// Runs the over-drive handshake HCLK above 168 MHz needs. The PLL must
+/// already be running, and the system clock not yet switched to it.
+/// Returns whether the regulator got there in time.
+fn enable_overdrive(pwr: &PWR) -> bool {
+    pwr.cr.modify(|_, w| w.oden().set_bit());
+    if !wait_for(|| pwr.csr.read().odrdy().bit_is_set()) {
+        return false;
+    }
+    pwr.cr.modify(|_, w| w.odswen().set_bit());
+    wait_for(|| pwr.csr.read().odswrdy().bit_is_set())
+}
+
+/// Takes the regulator out of over-drive. The system clock must already be
+/// off the PLL. A regulator that does not report leaving in time is left
+/// alone; over-drive costs power but runs any clock.
+fn disable_overdrive(pwr: &PWR) {
+    if pwr.cr.read().oden().bit_is_clear() {
+        return;
+    }
+    pwr.cr
+        .modify(|_, w| w.odswen().clear_bit().oden().clear_bit());
+    wait_for(|| pwr.csr.read().odswrdy().bit_is_clear());
+}
+
+/// The clock security system found the HSE stopped. The hardware has
+/// already moved the system clock to the HSI and stopped the PLL; bring
+/// the PLL back up from the HSI at the same clocks. If a switch is already
+/// under way that is left until it has finished.
+#[exception]
+unsafe fn NonMaskableInt() {
+    let rcc = unsafe { &*stm32f446::RCC::ptr() };
+    if rcc.cir.read().cssf().bit_is_clear() {
+        return;
+    }
+    rcc.cir.write(|w| w.cssc().set_bit());
+    rcc.cr.modify(|_, w| w.hseon().off());
 
-    while !rcc.cfgr.read().sws().is_pll() {}
+    if SWITCHING.load(Ordering::SeqCst) {
+        FALLBACK_PENDING.store(true, Ordering::SeqCst);
+    } else {
+        switch_system_clock(&HSI_FALLBACKS);
+    }
+    HSE_FAILED.store(true, Ordering::Relaxed);
 }
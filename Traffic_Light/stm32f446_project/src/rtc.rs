@@ -0,0 +1,114 @@
+use crate::timer_config::now;
+use stm32f4::stm32f446::{self, RTC};
+use traffic_core::schedule::{TimeOfDay, WeekTime, Weekday};
+use traffic_core::time::Duration;
+
+/// How long the LSE crystal gets to start; it can take two seconds.
+const LSE_STARTUP: Duration = Duration::from_millis(2_000);
+
+/// Starts the RTC at `start`, on the 32.768 kHz LSE crystal or, if that
+/// does not start in time, on the less accurate 32 kHz LSI. The RTC sits
+/// in the backup domain, so if it is already running from an earlier boot
+/// it is left alone and keeps its time. Returns whether it runs from the
+/// LSE.
+pub fn configure_rtc(start: WeekTime) -> bool {
+    let dp = unsafe { stm32f446::Peripherals::steal() };
+    let rcc = &dp.RCC;
+
+    rcc.apb1enr.modify(|_, w| w.pwren().enabled());
+    dp.PWR.cr.modify(|_, w| w.dbp().set_bit());
+
+    if rcc.bdcr.read().rtcen().is_enabled() {
+        return rcc.bdcr.read().rtcsel().is_lse();
+    }
+
+    rcc.bdcr.modify(|_, w| w.lseon().on());
+    let deadline = now() + LSE_STARTUP;
+    while rcc.bdcr.read().lserdy().is_not_ready() && now() < deadline {}
+    let lse = rcc.bdcr.read().lserdy().is_ready();
+    if lse {
+        rcc.bdcr.modify(|_, w| {
+            w.rtcsel().lse();
+            w.rtcen().enabled()
+        });
+    } else {
+        rcc.bdcr.modify(|_, w| w.lseon().off());
+        rcc.csr.modify(|_, w| w.lsion().on());
+        // Unbounded: the LSI is an on-chip RC oscillator that starts within
+        // a few tens of microseconds, and there is no clock left to try.
+        while rcc.csr.read().lsirdy().is_not_ready() {}
+        rcc.bdcr.modify(|_, w| {
+            w.rtcsel().lsi();
+            w.rtcen().enabled()
+        });
+    }
+
+    let rtc = &dp.RTC;
+    enter_init(rtc);
+    // 32768 Hz / (127 + 1) / (255 + 1) = 1 Hz, or from the LSI
+    // 32000 Hz / (127 + 1) / (249 + 1). The two dividers must be written
+    // separately.
+    let prediv_s = if lse { 255 } else { 249 };
+    rtc.prer.write(|w| w.prediv_s().bits(prediv_s));
+    rtc.prer.modify(|_, w| w.prediv_a().bits(127));
+    rtc.cr.modify(|_, w| w.fmt().clear_bit());
+    exit_init(rtc);
+
+    rtc_set_time(start);
+    lse
+}
+
+/// Sets the RTC to `now`, leaving its date alone.
+pub fn rtc_set_time(now: WeekTime) {
+    let dp = unsafe { stm32f446::Peripherals::steal() };
+    let rtc = &dp.RTC;
+    let time = now.time;
+
+    enter_init(rtc);
+    rtc.dr.modify(|_, w| unsafe { w.wdu().bits(now.day as u8) });
+    rtc.tr.write(|w| {
+        w.ht().bits(time.hour / 10);
+        w.hu().bits(time.hour % 10);
+        w.mnt().bits(time.minute / 10);
+        w.mnu().bits(time.minute % 10);
+        w.st().bits(time.second / 10);
+        w.su().bits(time.second % 10)
+    });
+    exit_init(rtc);
+}
+
+/// Current day of the week and time of day from the RTC.
+pub fn rtc_time() -> WeekTime {
+    let dp = unsafe { stm32f446::Peripherals::steal() };
+    let rtc = &dp.RTC;
+
+    while rtc.isr.read().rsf().is_not_synced() {}
+    // Reading TR freezes the shadow registers until DR is read, so the
+    // two belong to the same second.
+    let tr = rtc.tr.read();
+    let dr = rtc.dr.read();
+
+    WeekTime::new(
+        // WDU is never 0 once the calendar has been set.
+        Weekday::from_number(dr.wdu().bits()).unwrap_or(Weekday::Monday),
+        TimeOfDay::new(
+            tr.ht().bits() * 10 + tr.hu().bits(),
+            tr.mnt().bits() * 10 + tr.mnu().bits(),
+            tr.st().bits() * 10 + tr.su().bits(),
+        ),
+    )
+}
+
+fn enter_init(rtc: &RTC) {
+    rtc.wpr.write(|w| w.key().bits(0xCA));
+    rtc.wpr.write(|w| w.key().bits(0x53));
+    rtc.isr.modify(|_, w| w.init().init_mode());
+    while rtc.isr.read().initf().is_not_allowed() {}
+}
+
+fn exit_init(rtc: &RTC) {
+    rtc.isr.modify(|_, w| w.init().free_running_mode());
+    // The calendar must resynchronise before it is read again.
+    rtc.isr.modify(|_, w| w.rsf().clear());
+    rtc.wpr.write(|w| w.key().bits(0xFF));
+}
@@ -1,46 +1,98 @@
-use core::cell::RefCell;
-use cortex_m::interrupt::Mutex;
-use stm32f4::stm32f446::{self, TIM6};
+use core::sync::atomic::{AtomicU32, Ordering};
+use stm32f4::stm32f446::{self, NVIC, TIM2, interrupt};
+use stm32f446_clock::{Clocks, timer_prescaler};
+use traffic_core::time::{Duration, Instant};
 
-static G_TIM6: Mutex<RefCell<Option<TIM6>>> = Mutex::new(RefCell::new(None));
+use crate::constants::TESTING_FACTOR;
 
-pub fn configure_timer() {
-    let dp = unsafe { stm32f446::Peripherals::steal() };
+/// One controller second, shortened by `TESTING_FACTOR` for bench testing.
+pub const SECOND: Duration = Duration::from_millis(1000 / TESTING_FACTOR as u64);
+
+// TIM2 status flags; writing 0 clears one, writing 1 leaves it alone.
+const UIF: u32 = 1 << 0;
+const CC1IF: u32 = 1 << 1;
+
+/// Times the 32-bit counter has wrapped, the upper half of the time base.
+static OVERFLOWS: AtomicU32 = AtomicU32::new(0);
+
+fn tim2() -> &'static stm32f446::tim2::RegisterBlock {
+    unsafe { &*TIM2::ptr() }
+}
 
-    dp.RCC.apb1enr.modify(|_, w| w.tim6en().set_bit());
+/// Starts the monotonic time base: TIM2 counting microseconds across its
+/// full 32 bits, extended to 64 by counting overflows. Channel 1 serves as
+/// the alarm that wakes the main loop.
+pub fn configure_timer(clocks: &Clocks) {
+    let dp = unsafe { stm32f446::Peripherals::steal() };
 
-    dp.TIM6.psc.write(|w| w.psc().bits(90 - 1));
-    dp.TIM6.arr.write(|w| w.arr().bits(0xFFFF));
+    dp.RCC.apb1enr.modify(|_, w| w.tim2en().set_bit());
 
-    dp.TIM6.cr1.modify(|_, w| w.cen().set_bit());
+    let psc = timer_prescaler(clocks.timclk1(), 1_000_000);
+    dp.TIM2.psc.write(|w| w.psc().bits(psc as u16));
+    dp.TIM2.arr.write(|w| w.arr().bits(u32::MAX));
+    // Load the prescaler now rather than at the first overflow.
+    dp.TIM2.egr.write(|w| w.ug().set_bit());
+    dp.TIM2.sr.write(|w| unsafe { w.bits(!UIF) });
+    dp.TIM2.dier.write(|w| w.uie().set_bit());
 
-    while dp.TIM6.sr.read().uif().bit_is_clear() {}
+    dp.TIM2.cr1.modify(|_, w| w.cen().set_bit());
 
-    cortex_m::interrupt::free(|cs| {
-        G_TIM6.borrow(cs).replace(Some(dp.TIM6));
-    });
+    unsafe {
+        NVIC::unmask(interrupt::TIM2);
+    }
 }
 
-pub fn delay_us(us: u16) {
-    cortex_m::interrupt::free(|cs| {
-        if let Some(tim6) = G_TIM6.borrow(cs).borrow().as_ref() {
-            tim6.cnt.reset();
+/// Keeps the time base counting microseconds across a clock switch,
+/// without losing or repeating time.
+pub fn retime_timer(clocks: &Clocks) {
+    let tim2 = tim2();
+    let psc = timer_prescaler(clocks.timclk1(), 1_000_000);
+    let time = now();
+    tim2.psc.write(|w| w.psc().bits(psc as u16));
+    // Load the prescaler now without it counting as an overflow, then
+    // carry on from where the count was.
+    tim2.cr1.modify(|_, w| w.urs().set_bit());
+    tim2.egr.write(|w| w.ug().set_bit());
+    tim2.cr1.modify(|_, w| w.urs().clear_bit());
+    tim2.cnt.write(|w| w.cnt().bits(time.as_micros() as u32));
+}
 
-            while (tim6.cnt.read().cnt().bits() as u16) < us {
-                
-            }
+/// Current time on the monotonic time base.
+pub fn now() -> Instant {
+    cortex_m::interrupt::free(|_| {
+        let tim2 = tim2();
+        let mut high = OVERFLOWS.load(Ordering::Relaxed);
+        let low = tim2.cnt.read().cnt().bits();
+        // An overflow the interrupt has not counted yet. Only trust it if
+        // the count read is from after the wrap.
+        if tim2.sr.read().bits() & UIF != 0 && low < u32::MAX / 2 {
+            high += 1;
         }
-    });
+        Instant::from_micros((high as u64) << 32 | low as u64)
+    })
 }
 
-pub fn delay_ms(ms: u16) {
-    for _ in 0..ms {
-        delay_us(1000);
-    }
+/// Wakes the CPU at `deadline`, or early if it is more than one counter
+/// wrap away. Callers check the time again after waking.
+pub fn set_alarm(deadline: Instant) {
+    let tim2 = tim2();
+    tim2.ccr1()
+        .write(|w| w.ccr().bits(deadline.as_micros() as u32));
+    tim2.sr.write(|w| unsafe { w.bits(!CC1IF) });
+    tim2.dier.modify(|_, w| w.cc1ie().set_bit());
 }
 
-pub fn delay_s(s: u16) {
-    for _ in 0..s {
-        delay_ms(1000);
+#[interrupt]
+fn TIM2() {
+    let tim2 = tim2();
+    let flags = tim2.sr.read().bits();
+    if flags & UIF != 0 {
+        OVERFLOWS.fetch_add(1, Ordering::Relaxed);
+    }
+    // The alarm has done its job once the CPU is awake.
+    if flags & CC1IF != 0 {
+        tim2.dier.modify(|_, w| w.cc1ie().clear_bit());
     }
+    tim2.sr
+        .write(|w| unsafe { w.bits(!(flags & (UIF | CC1IF))) });
 }
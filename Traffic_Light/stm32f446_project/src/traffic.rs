@@ -1,54 +1,309 @@
-use core::sync::atomic::{AtomicBool, AtomicU8};
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicU16, AtomicU32, Ordering};
+use cortex_m::interrupt::Mutex;
+use traffic_core::blink::{BLINK_FLASH, Blinker};
+use traffic_core::controller::RailPreempt;
+use traffic_core::detector::{Detector, Thresholds};
+use traffic_core::events::EventLog;
+use traffic_core::monitor::ConflictMonitor;
+use traffic_core::pedestrian::{Crossing, PedHead, PedTiming};
+use traffic_core::phase::{
+    Approach, Intersection, Movement, Phase, PhaseDuration, Ring, Signal, SignalHead,
+};
+use traffic_core::schedule::{Days, Schedule, ScheduleEntry, TimeOfDay, WeekTime, Weekday};
+use traffic_core::timing::{ApproachTiming, STANDARD_PLAN, TimingPlan};
 
-pub static LEFT_TRAFFIC_INTENSITY_LEVEL: AtomicU8 = AtomicU8::new(0);
-pub static RIGHT_TRAFFIC_INTENSITY_LEVEL: AtomicU8 = AtomicU8::new(0);
+use crate::constants::*;
 
-pub static LEFT_INDICATOR_RATE: AtomicU8 = AtomicU8::new(0);
-pub static RIGHT_INDICATOR_RATE: AtomicU8 = AtomicU8::new(0);
+pub const LEFT: usize = 0;
+pub const RIGHT: usize = 1;
 
-pub static LEFT_BLINK_STATE: AtomicBool = AtomicBool::new(false);
-pub static RIGHT_BLINK_STATE: AtomicBool = AtomicBool::new(false);
-pub static LEFT_BLINK_COUNTER: AtomicU8 = AtomicU8::new(0);
-pub static RIGHT_BLINK_COUNTER: AtomicU8 = AtomicU8::new(0);
+pub const APPROACHES: [Approach; 2] = [
+    Approach {
+        through: SignalHead {
+            red: RED_LEFT,
+            yellow: YELLOW_LEFT,
+            green: GREEN_LEFT,
+        },
+        turn: None,
+        intensity: LEFT_TRAFFIC_INTENSITY,
+        // The left road is the side road. Its green only comes up when its
+        // loop calls and lasts as long as cars keep crossing it.
+        timing: Some(ApproachTiming {
+            min_green: 7,
+            passage: 3,
+            max_green: 30,
+        }),
+        flash: Signal::Red,
+    },
+    Approach {
+        through: SignalHead {
+            red: RED_RIGHT,
+            yellow: YELLOW_RIGHT,
+            green: GREEN_RIGHT,
+        },
+        turn: None,
+        intensity: RIGHT_TRAFFIC_INSTENSITY,
+        timing: None,
+        // The right road is the main road. It keeps the plans' timed green,
+        // resting in it while the left loop is quiet, and flashes yellow.
+        flash: Signal::Yellow,
+    },
+];
 
+pub const PHASES: [Phase; 2] = [
+    //right green, left red
+    Phase {
+        movements: &[Movement::Through(RIGHT)],
+        green: PhaseDuration::Delay(0),
+        yellow: PhaseDuration::Delay(1),
+    },
+    //left green, right red
+    Phase {
+        movements: &[Movement::Through(LEFT)],
+        green: PhaseDuration::Actuated,
+        yellow: PhaseDuration::Delay(3),
+    },
+];
 
+// The two approaches conflict, so a single ring serves them with a barrier
+// between each phase.
+pub const RINGS: [Ring; 1] = [Ring {
+    groups: &[&[0], &[1]],
+}];
 
-use crate::constants::*;
+// The crosswalk spans the left road, so it walks while the right has green.
+pub const CROSSINGS: [Crossing; 1] = [Crossing {
+    head: PedHead {
+        walk: PED_WALK,
+        dont_walk: PED_DONT_WALK,
+    },
+    button: PED_BUTTON,
+    phase: 0,
+    timing: PedTiming {
+        walk: 7,
+        clearance: 10,
+        buffer: 3,
+    },
+}];
+
+pub const INTERSECTION: Intersection = Intersection {
+    approaches: &APPROACHES,
+    phases: &PHASES,
+    rings: &RINGS,
+    crossings: &CROSSINGS,
+};
+
+/// A detector input and the approach it reports for.
+pub struct DetectorInput {
+    pub pin: u16,
+    pub approach: usize,
+}
+
+// The old intensity buttons are now the loop detectors of each approach; the
+// right road has a second lane with its own loop.
+pub const DETECTORS: [DetectorInput; 3] = [
+    DetectorInput {
+        pin: LEFT_TRAFFIC_INTENSITY,
+        approach: LEFT,
+    },
+    DetectorInput {
+        pin: RIGHT_TRAFFIC_INSTENSITY,
+        approach: RIGHT,
+    },
+    DetectorInput {
+        pin: RIGHT_SECOND_LANE_DETECTOR,
+        approach: RIGHT,
+    },
+];
+
+pub const DETECTOR_THRESHOLDS: Thresholds = Thresholds {
+    intense_occupancy: 25,
+    high_intense_occupancy: 50,
+    intense_volume: 15,
+    high_intense_volume: 30,
+};
+
+pub static VEHICLE_DETECTORS: Mutex<RefCell<[Detector; DETECTORS.len()]>> = Mutex::new(
+    RefCell::new([Detector::new(DETECTOR_SAMPLES_PER_BIN); DETECTORS.len()]),
+);
+
+/// Vehicles not yet handed to the controller, one bit per approach.
+pub static DETECTIONS: AtomicU32 = AtomicU32::new(0);
+
+// Commuters head in along the right road in the morning and back out along
+// the left in the evening. Delays are [right green, right yellow, left green,
+// left yellow]; the left green is actuated, so its entry goes unused.
+pub const AM_PEAK_PLAN: TimingPlan = TimingPlan {
+    normal: [30, 5, 15, 5],
+    left_intense: [20, 5, 30, 5],
+    right_intense: [45, 5, 10, 5],
+    right_high_intense: [60, 5, 10, 5],
+    ..STANDARD_PLAN
+};
+pub const OFF_PEAK_PLAN: TimingPlan = STANDARD_PLAN;
+pub const PM_PEAK_PLAN: TimingPlan = TimingPlan {
+    normal: [15, 5, 30, 5],
+    right_intense: [30, 5, 20, 5],
+    left_intense: [10, 5, 45, 5],
+    left_high_intense: [10, 5, 60, 5],
+    ..STANDARD_PLAN
+};
+// Short greens and a longer all-red for fast, sparse night traffic.
+pub const NIGHT_PLAN: TimingPlan = TimingPlan {
+    normal: [10, 4, 10, 4],
+    all_red: 3,
+    ..STANDARD_PLAN
+};
+
+/// Time the RTC starts from when the backup domain has lost power.
+pub const RTC_START_TIME: WeekTime = WeekTime::new(Weekday::Monday, TimeOfDay::new(0, 0, 0));
+
+// Peaks on weekdays only; the weekend goes from the night plan straight to
+// off-peak.
+pub const SCHEDULE: Schedule = Schedule {
+    entries: &[
+        ScheduleEntry {
+            days: Days::EVERY_DAY,
+            start: TimeOfDay::new(1, 0, 0),
+            plan: &NIGHT_PLAN,
+            flash: true,
+        },
+        ScheduleEntry {
+            days: Days::EVERY_DAY,
+            start: TimeOfDay::new(5, 0, 0),
+            plan: &NIGHT_PLAN,
+            flash: false,
+        },
+        ScheduleEntry {
+            days: Days::WEEKDAYS,
+            start: TimeOfDay::new(6, 30, 0),
+            plan: &AM_PEAK_PLAN,
+            flash: false,
+        },
+        ScheduleEntry {
+            days: Days::WEEKEND,
+            start: TimeOfDay::new(8, 0, 0),
+            plan: &OFF_PEAK_PLAN,
+            flash: false,
+        },
+        ScheduleEntry {
+            days: Days::WEEKDAYS,
+            start: TimeOfDay::new(9, 30, 0),
+            plan: &OFF_PEAK_PLAN,
+            flash: false,
+        },
+        ScheduleEntry {
+            days: Days::WEEKDAYS,
+            start: TimeOfDay::new(16, 0, 0),
+            plan: &PM_PEAK_PLAN,
+            flash: false,
+        },
+        ScheduleEntry {
+            days: Days::WEEKDAYS,
+            start: TimeOfDay::new(19, 0, 0),
+            plan: &OFF_PEAK_PLAN,
+            flash: false,
+        },
+        ScheduleEntry {
+            days: Days::EVERY_DAY,
+            start: TimeOfDay::new(22, 0, 0),
+            plan: &NIGHT_PLAN,
+            flash: false,
+        },
+    ],
+};
+
+// The conflict monitor gets its own description of the heads rather than
+// reading the phase table, so a mistake there cannot hide a conflict.
+pub const MONITORED_HEADS: [SignalHead; 2] = [
+    SignalHead {
+        red: RED_LEFT,
+        yellow: YELLOW_LEFT,
+        green: GREEN_LEFT,
+    },
+    SignalHead {
+        red: RED_RIGHT,
+        yellow: YELLOW_RIGHT,
+        green: GREEN_RIGHT,
+    },
+];
+// Left and right conflict; each is only compatible with itself.
+pub const COMPATIBILITY: [u16; 2] = [0b01, 0b10];
+
+pub static MONITOR: Mutex<RefCell<ConflictMonitor>> = Mutex::new(RefCell::new(
+    ConflictMonitor::new(&MONITORED_HEADS, &COMPATIBILITY),
+));
+
+// Per-approach state below is indexed like `APPROACHES`.
+pub static INTENSITY_LEVELS: [AtomicU8; APPROACHES.len()] =
+    [const { AtomicU8::new(0) }; APPROACHES.len()];
+
+pub const INDICATORS: [u16; APPROACHES.len()] = [LEFT_TRAFFIC_INDICATOR, RIGHT_TRAFFIC_INDICATOR];
+pub static INDICATOR_BLINKERS: Mutex<RefCell<[Blinker; APPROACHES.len()]>> =
+    Mutex::new(RefCell::new([Blinker::new(); APPROACHES.len()]));
+
+/// Push-button presses not yet handed to the controller, one bit per crossing.
+pub static PED_CALLS: AtomicU16 = AtomicU16::new(0);
+
+/// An emergency preemption input, the approach it calls and the phase the
+/// normal sequence resumes at afterwards.
+pub struct PreemptInput {
+    pub pin: u16,
+    pub approach: usize,
+    pub reentry: usize,
+}
+
+// The right road already had its green during preemption, so pick up again
+// with the left.
+pub const PREEMPTS: [PreemptInput; 1] = [PreemptInput {
+    pin: PREEMPT_RIGHT,
+    approach: RIGHT,
+    reentry: 1,
+}];
+
+/// Debounced state of the preemption inputs, one bit per input.
+pub static PREEMPT_CALLS: AtomicU16 = AtomicU16::new(0);
+
+// The tracks cross the left road just behind its stop line. Its queue is
+// cleared off them first, then the right road runs on its own until the gates
+// lift, and the left picks up again with the vehicles held at the gates.
+pub const RAIL: RailPreempt = RailPreempt {
+    track_clearance: 1,
+    clearance_green: 15,
+    hold: &[0],
+    reentry: 1,
+};
+
+/// Whether the gate input reports a train. Starts set so nothing crosses the
+/// tracks until the input has been read.
+pub static TRAIN_PRESENT: AtomicBool = AtomicBool::new(true);
+
+/// Controller and clock events with the time they happened.
+pub static EVENT_LOG: Mutex<RefCell<EventLog>> = Mutex::new(RefCell::new(EventLog::new()));
+
+/// Set by the flash button; the main loop toggles flash mode.
+pub static FLASH_COMMAND: AtomicBool = AtomicBool::new(false);
+
+/// Drives every lamp the controller leaves flashing.
+pub static FLASHER: Mutex<RefCell<Blinker>> =
+    Mutex::new(RefCell::new(Blinker::with_rate(BLINK_FLASH)));
+
+/// Detector wired to `pin`.
+pub fn detector_for_pin(pin: u16) -> Option<usize> {
+    DETECTORS.iter().position(|detector| detector.pin == pin)
+}
+
+pub fn intensity_levels() -> [u8; APPROACHES.len()] {
+    core::array::from_fn(|approach| INTENSITY_LEVELS[approach].load(Ordering::Relaxed))
+}
+
+/// Preemption input wired to `pin`.
+pub fn preempt_for_pin(pin: u16) -> Option<usize> {
+    PREEMPTS.iter().position(|input| input.pin == pin)
+}
 
-pub fn get_traffic_delays(left_intensity: u8, right_intensity: u8) -> [u16; 4] {
-    let delay_normal: [u16; 4] = [15, 5, 15, 5];
-    let delay_left_intense: [u16; 4] = [10, 5, 30, 5];
-    let delay_left_high_intense: [u16; 4] = [10, 5, 50, 5];
-    let delay_right_intense: [u16; 4] = [30, 5, 10, 5];
-    let delay_right_high_intense: [u16; 4] = [50, 5, 10, 5];
-
-    if left_intensity == right_intensity {
-        delay_normal
-    } else if left_intensity == NORMAL {
-        if right_intensity == INTENSE {
-            delay_right_intense
-        } else if right_intensity == HIGH_INTENSE {
-            delay_right_high_intense
-        } else {
-            delay_normal
-        }
-    } else if left_intensity == INTENSE {
-        if right_intensity == NORMAL {
-            delay_left_intense
-        } else if right_intensity == HIGH_INTENSE {
-            delay_right_intense
-        } else {
-            delay_normal
-        }
-    } else if left_intensity == HIGH_INTENSE {
-        if right_intensity == NORMAL {
-            delay_left_high_intense
-        } else if right_intensity == INTENSE {
-            delay_left_intense
-        } else {
-            delay_normal
-        }
-    } else {
-        delay_normal
-    }
-}
\ No newline at end of file
+/// Crossing whose push button is wired to `pin`.
+pub fn crossing_for_button(pin: u16) -> Option<usize> {
+    CROSSINGS.iter().position(|crossing| crossing.button == pin)
+}
@@ -0,0 +1,99 @@
+use crate::time::{Duration, Instant};
+
+// Blink rates
+pub const BLINK_OFF: u8 = 0;
+pub const BLINK_SLOW: u8 = 1;
+pub const BLINK_MEDIUM: u8 = 2;
+pub const BLINK_FAST: u8 = 3;
+/// Traffic signal flash: about once a second, lit half the time.
+pub const BLINK_FLASH: u8 = 4;
+
+/// Ticks in one full blink cycle. The fast and slow periods divide into
+/// it; the medium period of 8 does not, so the cycle cuts its dark half
+/// short and medium blinks lit 8 ticks, dark 4, as it always has.
+const CYCLE_TICKS: u8 = 12;
+
+/// Time between blink ticks.
+pub const BLINK_TICK: Duration = Duration::from_millis(100);
+
+/// Ticks a flashing signal stays in each state; one flash a second.
+const FLASH_TICKS: u8 = 5;
+
+/// Blink state of one indicator lamp, advanced once per [`BLINK_TICK`].
+#[derive(Clone, Copy)]
+pub struct Blinker {
+    rate: u8,
+    counter: u8,
+    state: bool,
+    /// When [`Blinker::update`] next runs a tick.
+    next_tick: Option<Instant>,
+}
+
+impl Blinker {
+    pub const fn new() -> Self {
+        Self::with_rate(BLINK_OFF)
+    }
+
+    pub const fn with_rate(rate: u8) -> Self {
+        Blinker {
+            rate,
+            counter: 0,
+            state: false,
+            next_tick: None,
+        }
+    }
+
+    pub fn rate(&self) -> u8 {
+        self.rate
+    }
+
+    /// Changes the blink rate. Turning blinking off leaves the lamp dark,
+    /// so the next blink starts by switching it on.
+    pub fn set_rate(&mut self, rate: u8) {
+        self.rate = rate;
+        if rate == BLINK_OFF {
+            self.state = false;
+        }
+    }
+
+    /// Advances one tick. Returns the new lamp state when the lamp toggles.
+    pub fn tick(&mut self) -> Option<bool> {
+        let (period, cycle) = match self.rate {
+            BLINK_FAST => (3, CYCLE_TICKS),
+            BLINK_MEDIUM => (8, CYCLE_TICKS),
+            BLINK_SLOW => (6, CYCLE_TICKS),
+            BLINK_FLASH => (FLASH_TICKS, 2 * FLASH_TICKS),
+            _ => return None,
+        };
+
+        let toggle = self.counter.is_multiple_of(period);
+        self.counter = (self.counter + 1) % cycle;
+
+        if toggle {
+            self.state = !self.state;
+            Some(self.state)
+        } else {
+            None
+        }
+    }
+
+    /// Runs every tick due by `now`, the first at the first call, so the
+    /// blink keeps time however late it is called. Returns the lamp state
+    /// if it toggled.
+    pub fn update(&mut self, now: Instant) -> Option<bool> {
+        let mut next = self.next_tick.unwrap_or(now);
+        let mut toggled = None;
+        while next <= now {
+            toggled = self.tick().or(toggled);
+            next += BLINK_TICK;
+        }
+        self.next_tick = Some(next);
+        toggled
+    }
+}
+
+impl Default for Blinker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
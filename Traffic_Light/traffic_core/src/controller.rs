@@ -0,0 +1,924 @@
+use crate::hal::LampOutput;
+use crate::pedestrian::{MAX_CROSSINGS, PedSignal, PedState};
+pub use crate::phase::MAX_APPROACHES;
+use crate::phase::{Intersection, Movement, Phase, PhaseDuration, Signal};
+use crate::timing::TimingPlan;
+
+/// Most rings a controller runs side by side.
+pub const MAX_RINGS: usize = 2;
+
+/// Seconds between updates while flashing, when nothing is being timed.
+const FLASH_POLL: u16 = 1;
+
+/// Why the intersection is flashing.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FlashCause {
+    Schedule,
+    Command,
+    Fault,
+}
+
+/// Where an emergency-vehicle preemption has got to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PreemptStage {
+    /// Conflicting greens are running their yellow and all-red.
+    Clearing,
+    /// The preempted approach is held green.
+    Dwell,
+    /// The call has cleared and the dwell green is ending.
+    Exit,
+}
+
+/// Railroad preemption for an intersection next to a level crossing.
+pub struct RailPreempt<'a> {
+    /// Phase whose green lets vehicles queued over the tracks drive off.
+    pub track_clearance: usize,
+    /// Seconds the track-clearance green runs, whatever else is going on.
+    pub clearance_green: u16,
+    /// Phases allowed to run while the gates are down, in turn. None of
+    /// them may send traffic over the tracks. A single phase rests green;
+    /// with none the intersection waits in red.
+    pub hold: &'a [usize],
+    /// Phase the normal sequence resumes at once the gates lift.
+    pub reentry: usize,
+}
+
+/// Where a railroad preemption has got to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RailStage {
+    /// Greens other than the track clearance are running their yellow and
+    /// all-red.
+    Clearing,
+    /// The track-clearance green is running.
+    TrackClearance,
+    /// Only the hold phases run while the train passes.
+    Hold,
+    /// The gates have lifted and the hold green is ending.
+    Exit,
+}
+
+#[derive(Clone, Copy)]
+struct Rail<'a> {
+    config: &'a RailPreempt<'a>,
+    stage: RailStage,
+    /// Entry of `config.hold` last served.
+    hold: usize,
+    train: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Preempt {
+    approach: usize,
+    /// Phase that serves the approach and is held green.
+    dwell: usize,
+    /// Phase the normal sequence resumes at.
+    reentry: usize,
+    stage: PreemptStage,
+    called: bool,
+}
+
+/// What a ring is currently timing.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Interval {
+    Green,
+    Yellow,
+    /// Red clearance after a yellow. Rings cross a barrier together, so
+    /// there the whole intersection is red while the box clears.
+    AllRed,
+    /// Cleared and waiting for the other rings at the barrier.
+    Red,
+}
+
+#[derive(Clone, Copy)]
+struct RingState {
+    position: usize,
+    interval: Interval,
+    remaining: u16,
+    /// Seconds the current green has been showing.
+    green_time: u16,
+    /// Seconds since a detector of the current green last saw a vehicle.
+    gap: u16,
+}
+
+impl RingState {
+    const fn new() -> Self {
+        RingState {
+            position: 0,
+            interval: Interval::Red,
+            remaining: 0,
+            green_time: 0,
+            gap: u16::MAX,
+        }
+    }
+}
+
+/// Ring-and-barrier sequencer for one intersection.
+///
+/// The caller shows the lamps through [`Controller::update`], waits for the
+/// number of seconds it returns and calls it again with the time that
+/// actually passed.
+pub struct Controller<'a> {
+    intersection: &'a Intersection<'a>,
+    plan: TimingPlan,
+    /// Plan waiting to take over at the start of the next cycle.
+    next_plan: Option<TimingPlan>,
+    group: usize,
+    rings: [RingState; MAX_RINGS],
+    peds: [PedState; MAX_CROSSINGS],
+    /// Latched push-button calls, one bit per crossing.
+    ped_calls: u16,
+    /// Approaches with a vehicle waiting on red, one bit each.
+    demand: u32,
+    /// Vehicles reported since the last update, one bit per approach.
+    detections: u32,
+    flash: Option<FlashCause>,
+    preempt: Option<Preempt>,
+    rail: Option<Rail<'a>>,
+    /// Flash waiting to start at the end of the current cycle.
+    flash_request: Option<FlashCause>,
+    started: bool,
+}
+
+impl<'a> Controller<'a> {
+    pub fn new(intersection: &'a Intersection<'a>, plan: TimingPlan) -> Self {
+        // There must be a ring, with a group, for the cycle to run through.
+        assert!(!intersection.rings.is_empty() && intersection.groups() != 0);
+        assert!(intersection.rings.len() <= MAX_RINGS);
+        assert!(intersection.crossings.len() <= MAX_CROSSINGS);
+        assert!(intersection.approaches.len() <= MAX_APPROACHES);
+        // Every movement must name one of those approaches, so it has a
+        // demand bit.
+        assert!(intersection.phases.iter().all(|phase| {
+            phase
+                .movements
+                .iter()
+                .all(|movement| movement.approach() < intersection.approaches.len())
+        }));
+        Controller {
+            intersection,
+            plan,
+            next_plan: None,
+            group: 0,
+            rings: [RingState::new(); MAX_RINGS],
+            peds: [PedState::new(); MAX_CROSSINGS],
+            ped_calls: 0,
+            demand: Self::call_all(intersection),
+            detections: 0,
+            flash: None,
+            preempt: None,
+            rail: None,
+            flash_request: None,
+            started: false,
+        }
+    }
+
+    /// Plan the intersection is running on.
+    pub fn plan(&self) -> &TimingPlan {
+        &self.plan
+    }
+
+    /// Switches to `plan` when the current cycle ends, so no phase is cut
+    /// short or stretched part-way through.
+    pub fn set_plan(&mut self, plan: TimingPlan) {
+        self.next_plan = Some(plan);
+    }
+
+    /// Why the intersection is flashing, if it is.
+    pub fn flashing(&self) -> Option<FlashCause> {
+        self.flash
+    }
+
+    /// Puts the intersection into flash. A fault, or a request before the
+    /// first update, flashes at once; otherwise the current cycle is
+    /// finished first so no green is cut short. A fault while already
+    /// flashing for another cause takes over as the cause, so it is not
+    /// cleared along with that one.
+    pub fn enter_flash(&mut self, cause: FlashCause) {
+        if self.flash.is_some() {
+            if cause == FlashCause::Fault {
+                self.flash = Some(cause);
+            }
+            return;
+        }
+        if cause == FlashCause::Fault || !self.started {
+            self.flash = Some(cause);
+            self.flash_request = None;
+            self.preempt = None;
+            self.rail = None;
+            self.rings = [RingState::new(); MAX_RINGS];
+            self.peds = [PedState::new(); MAX_CROSSINGS];
+        } else {
+            self.flash_request = Some(cause);
+        }
+    }
+
+    /// Leaves flash through the start-up sequence: steady red everywhere
+    /// for the plan's start-up time, then a cycle serving every approach.
+    pub fn leave_flash(&mut self) {
+        self.flash_request = None;
+        if self.flash.take().is_none() {
+            return;
+        }
+
+        // Set every ring up as if clearing the last group, so the
+        // sequence carries on into group 0 when the red runs out.
+        self.started = true;
+        self.demand |= Self::call_all(self.intersection);
+        self.group = self.intersection.groups() - 1;
+        for ring in 0..self.ring_count() {
+            self.rings[ring] = RingState {
+                position: self.sequence(ring).len() - 1,
+                interval: Interval::AllRed,
+                remaining: self.plan.startup_red,
+                ..RingState::new()
+            };
+        }
+    }
+
+    /// Starts preemption for an emergency vehicle coming on `approach`.
+    /// Conflicting greens end at once, though their yellow, all-red and
+    /// pedestrian clearance still run in full. The approach is then held
+    /// green until [`Controller::clear_preempt`], after which the normal
+    /// sequence resumes at `reentry`. Ignored while flashing, during
+    /// railroad preemption or while another approach holds the preemption.
+    pub fn preempt(&mut self, approach: usize, reentry: usize) {
+        if self.flash.is_some() || !self.started || self.rail.is_some() {
+            return;
+        }
+        if let Some(preempt) = &mut self.preempt {
+            if preempt.approach == approach {
+                preempt.called = true;
+                preempt.reentry = reentry;
+            }
+            return;
+        }
+        let Some(dwell) = self
+            .intersection
+            .phases
+            .iter()
+            .position(|phase| phase.serves(Movement::Through(approach)))
+        else {
+            return;
+        };
+
+        self.end_greens(dwell);
+        self.preempt = Some(Preempt {
+            approach,
+            dwell,
+            reentry,
+            stage: PreemptStage::Clearing,
+            called: true,
+        });
+    }
+
+    /// Drops the preemption call for `approach`.
+    pub fn clear_preempt(&mut self, approach: usize) {
+        if let Some(preempt) = &mut self.preempt
+            && preempt.approach == approach
+        {
+            preempt.called = false;
+        }
+    }
+
+    /// Preempted approach and how far the preemption has got, if one is
+    /// running.
+    pub fn preemption(&self) -> Option<(usize, PreemptStage)> {
+        self.preempt
+            .map(|preempt| (preempt.approach, preempt.stage))
+    }
+
+    /// Starts railroad preemption, or keeps it going while the gate input
+    /// says a train is coming. Call it for as long as the input is active;
+    /// the caller is expected to treat a lost input as active too.
+    ///
+    /// Greens other than the track clearance end as for emergency
+    /// preemption, then the track-clearance green runs in full, and only
+    /// the hold phases run until [`Controller::clear_rail_preempt`].
+    /// Railroad preemption overrides any emergency preemption.
+    pub fn rail_preempt(&mut self, config: &'a RailPreempt<'a>) {
+        if self.flash.is_some() || !self.started {
+            return;
+        }
+        if let Some(rail) = &mut self.rail {
+            rail.train = true;
+            // A second train during the exit clears the tracks again.
+            if rail.stage == RailStage::Exit {
+                rail.stage = RailStage::Clearing;
+            }
+            return;
+        }
+
+        self.preempt = None;
+        self.end_greens(config.track_clearance);
+        self.rail = Some(Rail {
+            config,
+            stage: RailStage::Clearing,
+            hold: 0,
+            train: true,
+        });
+    }
+
+    /// Notes that the gates have lifted. The hold green ends and the normal
+    /// sequence resumes; a track clearance already started still runs out.
+    pub fn clear_rail_preempt(&mut self) {
+        if let Some(rail) = &mut self.rail {
+            rail.train = false;
+        }
+    }
+
+    /// How far railroad preemption has got, if it is running.
+    pub fn rail_preemption(&self) -> Option<RailStage> {
+        self.rail.map(|rail| rail.stage)
+    }
+
+    /// Barrier group currently being served.
+    pub fn group(&self) -> usize {
+        self.group
+    }
+
+    /// Phase and interval each ring is timing.
+    pub fn active(&self) -> impl Iterator<Item = (usize, Interval)> + '_ {
+        (0..self.intersection.rings.len()).map(|ring| {
+            let state = &self.rings[ring];
+            (self.phase_index(ring, state.position), state.interval)
+        })
+    }
+
+    /// Indication currently due for `movement`.
+    pub fn signal(&self, movement: Movement) -> Signal {
+        let mut signal = Signal::Red;
+        for (phase, interval) in self.active() {
+            if !self.intersection.phases[phase].serves(movement) {
+                continue;
+            }
+            match interval {
+                Interval::Green => return Signal::Green,
+                Interval::Yellow => signal = Signal::Yellow,
+                Interval::AllRed | Interval::Red => {}
+            }
+        }
+        signal
+    }
+
+    /// Latches push-button calls, one bit per crossing. A call is served
+    /// the next time its crossing's phase turns green.
+    pub fn call_pedestrians(&mut self, calls: u16) {
+        self.ped_calls |= calls;
+    }
+
+    /// Calls waiting to be served, one bit per crossing.
+    pub fn ped_calls(&self) -> u16 {
+        self.ped_calls
+    }
+
+    /// Records vehicles seen since the last update, one bit per approach.
+    /// At the next update a vehicle on green extends the green; one on red
+    /// or yellow places a call that is held until its approach is next
+    /// served.
+    pub fn detect(&mut self, approaches: u32) {
+        self.detections |= approaches;
+    }
+
+    /// Approaches with a call waiting, one bit each.
+    pub fn demand(&self) -> u32 {
+        self.demand
+    }
+
+    /// Indication currently due for `crossing`.
+    pub fn ped_signal(&self, crossing: usize) -> PedSignal {
+        self.peds[crossing].signal()
+    }
+
+    /// Accounts for `elapsed` seconds, moves through every interval that
+    /// has ended and drives the lamps. Returns the seconds until the next
+    /// interval ends.
+    pub fn update(&mut self, elapsed: u16, intensities: &[u8], lamps: &mut impl LampOutput) -> u16 {
+        if self.started {
+            for ring in self.rings.iter_mut() {
+                ring.remaining = ring.remaining.saturating_sub(elapsed);
+                ring.gap = ring.gap.saturating_add(elapsed);
+                if ring.interval == Interval::Green {
+                    ring.green_time = ring.green_time.saturating_add(elapsed);
+                }
+            }
+            for ped in self.peds.iter_mut() {
+                ped.remaining = ped.remaining.saturating_sub(elapsed);
+            }
+        } else {
+            self.started = true;
+            if self.flash.is_none() {
+                self.start_group(0, intensities);
+            }
+        }
+
+        if self.flash.is_some() {
+            self.show_flash(lamps);
+            return FLASH_POLL;
+        }
+
+        self.take_detections();
+        while self.advance(intensities) {}
+        if self.flash.is_some() {
+            self.show_flash(lamps);
+            return FLASH_POLL;
+        }
+        self.show(lamps);
+        self.next_event()
+    }
+
+    /// A call on every approach, so the first cycle after start-up serves
+    /// them all.
+    fn call_all(intersection: &Intersection) -> u32 {
+        (0..intersection.approaches.len()).fold(0, |mask, approach| mask | 1 << approach)
+    }
+
+    fn ring_count(&self) -> usize {
+        self.intersection.rings.len()
+    }
+
+    fn sequence(&self, ring: usize) -> &'a [usize] {
+        self.intersection.rings[ring].groups[self.group]
+    }
+
+    fn phase_index(&self, ring: usize, position: usize) -> usize {
+        self.sequence(ring)[position]
+    }
+
+    fn phase(&self, ring: usize) -> &'a Phase<'a> {
+        &self.intersection.phases[self.phase_index(ring, self.rings[ring].position)]
+    }
+
+    fn is_last(&self, ring: usize) -> bool {
+        self.rings[ring].position + 1 == self.sequence(ring).len()
+    }
+
+    /// Approaches currently shown green, one bit each.
+    fn green_approaches(&self) -> u32 {
+        self.active()
+            .filter(|&(_, interval)| interval == Interval::Green)
+            .fold(0, |mask, (phase, _)| {
+                mask | self.intersection.phases[phase].approaches()
+            })
+    }
+
+    fn take_detections(&mut self) {
+        let detections = core::mem::take(&mut self.detections);
+        for ring in 0..self.ring_count() {
+            if self.rings[ring].interval == Interval::Green
+                && self.phase(ring).approaches() & detections != 0
+            {
+                self.rings[ring].gap = 0;
+            }
+        }
+        self.demand |= detections & !self.green_approaches();
+    }
+
+    /// Whether `phase` has anyone to serve. Phases that are not actuated
+    /// always do.
+    fn has_demand(&self, phase: usize) -> bool {
+        let config = &self.intersection.phases[phase];
+        if !matches!(config.green, PhaseDuration::Actuated) {
+            return true;
+        }
+        let ped_call = self
+            .intersection
+            .crossings
+            .iter()
+            .enumerate()
+            .any(|(crossing, config)| {
+                config.phase == phase && self.ped_calls & (1 << crossing) != 0
+            });
+        config.approaches() & self.demand != 0 || ped_call
+    }
+
+    /// Whether a phase that is not green is waiting to be served.
+    fn conflicting_demand(&self) -> bool {
+        (0..self.intersection.phases.len()).any(|phase| {
+            let green = self
+                .active()
+                .any(|(active, interval)| active == phase && interval == Interval::Green);
+            !green && self.has_demand(phase)
+        })
+    }
+
+    /// Seconds a green goes on for once its current stretch has run out, or
+    /// 0 if it should end.
+    fn extension(&self, ring: usize) -> u16 {
+        let timing = self.phase(ring).actuated(self.intersection.approaches);
+        // With nobody else waiting the green rests, checking again every
+        // passage, or every second if it is not actuated. That lets a timed
+        // main road keep its green while actuated side roads are empty. A
+        // pedestrian call or a flash request still ends the cycle.
+        let waiting =
+            self.conflicting_demand() || self.ped_calls != 0 || self.flash_request.is_some();
+        if !waiting {
+            return timing.map_or(1, |timing| timing.passage.max(1));
+        }
+        let Some(timing) = timing else {
+            return 0;
+        };
+        let state = &self.rings[ring];
+        let passage = timing.passage.saturating_sub(state.gap);
+        let max_out = timing.max_green.saturating_sub(state.green_time);
+        passage.min(max_out)
+    }
+
+    fn start_group(&mut self, group: usize, intensities: &[u8]) {
+        self.group = group;
+        for ring in 0..self.ring_count() {
+            self.rings[ring].position = 0;
+            self.start_green(ring, intensities);
+        }
+    }
+
+    fn start_green(&mut self, ring: usize, intensities: &[u8]) {
+        // Actuated phases nobody is waiting for are skipped. A ring with
+        // nothing left to serve in this group waits at the barrier.
+        while !self.has_demand(self.phase_index(ring, self.rings[ring].position)) {
+            if self.is_last(ring) {
+                self.rings[ring].interval = Interval::Red;
+                self.rings[ring].remaining = 0;
+                return;
+            }
+            self.rings[ring].position += 1;
+        }
+
+        let index = self.phase_index(ring, self.rings[ring].position);
+        let phase = self.phase(ring);
+        let mut green = self.green_seconds(index, intensities);
+        self.demand &= !phase.approaches();
+
+        // Called crossings walk with the phase, which stays green until
+        // their clearance and buffer are over.
+        for (crossing, config) in self.intersection.crossings.iter().enumerate() {
+            if config.phase == index && self.ped_calls & (1 << crossing) != 0 {
+                self.ped_calls &= !(1 << crossing);
+                self.peds[crossing].start(&config.timing);
+                green = green.max(config.timing.total());
+            }
+        }
+
+        let state = &mut self.rings[ring];
+        state.interval = Interval::Green;
+        state.remaining = green;
+        state.green_time = 0;
+        state.gap = u16::MAX;
+    }
+
+    /// Ends every green except `keep`'s at once. Walks on those phases
+    /// are cut short, but their clearance still runs before the yellow.
+    fn end_greens(&mut self, keep: usize) {
+        for ring in 0..self.ring_count() {
+            let index = self.phase_index(ring, self.rings[ring].position);
+            if self.rings[ring].interval != Interval::Green || index == keep {
+                continue;
+            }
+            let mut clearance = 0;
+            for (crossing, config) in self.intersection.crossings.iter().enumerate() {
+                if config.phase == index {
+                    clearance = clearance.max(self.peds[crossing].end_walk(&config.timing));
+                }
+            }
+            self.rings[ring].remaining = clearance;
+        }
+    }
+
+    /// Shows `phase` green for `green` seconds with every other ring red.
+    fn serve_only(&mut self, phase: usize, green: u16) {
+        let Some((served, group, position)) = self.locate(phase) else {
+            return;
+        };
+        self.group = group;
+        for ring in 0..self.ring_count() {
+            if ring == served {
+                let state = &mut self.rings[ring];
+                state.position = position;
+                state.interval = Interval::Green;
+                state.remaining = green;
+                state.green_time = 0;
+                state.gap = u16::MAX;
+            } else {
+                self.rings[ring].position = self.sequence(ring).len() - 1;
+                self.rings[ring].interval = Interval::Red;
+                self.rings[ring].remaining = 0;
+            }
+        }
+        self.demand &= !self.intersection.phases[phase].approaches();
+    }
+
+    /// Seconds `phase` shows green when it starts, before any extension.
+    fn green_seconds(&self, phase: usize, intensities: &[u8]) -> u16 {
+        let phase = &self.intersection.phases[phase];
+        match phase.actuated(self.intersection.approaches) {
+            Some(timing) => timing.min_green,
+            None => phase.seconds(phase.green, &self.plan, intensities),
+        }
+    }
+
+    fn start_yellow(&mut self, ring: usize, intensities: &[u8]) {
+        let phase = self.phase(ring);
+        self.rings[ring].interval = Interval::Yellow;
+        self.rings[ring].remaining = phase.seconds(phase.yellow, &self.plan, intensities);
+    }
+
+    /// Ring, group and position at which `phase` is served.
+    fn locate(&self, phase: usize) -> Option<(usize, usize, usize)> {
+        self.intersection
+            .rings
+            .iter()
+            .enumerate()
+            .find_map(|(ring, config)| {
+                config
+                    .groups
+                    .iter()
+                    .enumerate()
+                    .find_map(|(group, sequence)| {
+                        let position = sequence.iter().position(|&index| index == phase)?;
+                        Some((ring, group, position))
+                    })
+            })
+    }
+
+    /// Makes every transition that is due. Returns whether anything changed.
+    fn advance(&mut self, intensities: &[u8]) -> bool {
+        let mut changed = false;
+
+        for (ped, config) in self.peds.iter_mut().zip(self.intersection.crossings) {
+            changed |= ped.advance(&config.timing);
+        }
+
+        if let Some(rail) = self.rail {
+            return self.advance_rail(rail, intensities) || changed;
+        }
+        if let Some(preempt) = self.preempt {
+            return self.advance_preempt(preempt, intensities) || changed;
+        }
+
+        for ring in 0..self.ring_count() {
+            if self.rings[ring].remaining > 0 {
+                continue;
+            }
+            match self.rings[ring].interval {
+                Interval::Green => {
+                    let extension = self.extension(ring);
+                    if extension > 0 {
+                        self.rings[ring].remaining = extension;
+                        changed = true;
+                    } else if !self.is_last(ring) {
+                        // The last green of a group holds until every ring
+                        // reaches it.
+                        self.start_yellow(ring, intensities);
+                        changed = true;
+                    }
+                }
+                Interval::Yellow => {
+                    self.rings[ring].interval = Interval::AllRed;
+                    self.rings[ring].remaining = self.plan.all_red;
+                    changed = true;
+                }
+                Interval::AllRed if self.is_last(ring) => {
+                    self.rings[ring].interval = Interval::Red;
+                    changed = true;
+                }
+                Interval::AllRed => {
+                    self.rings[ring].position += 1;
+                    self.start_green(ring, intensities);
+                    changed = true;
+                }
+                _ => {}
+            }
+        }
+
+        // A ring that skipped the rest of its group is already red at the
+        // barrier; the others end their greens together.
+        let rings = 0..self.ring_count();
+        let at_barrier = |interval| {
+            rings.clone().all(|ring| {
+                let state = &self.rings[ring];
+                self.is_last(ring)
+                    && (state.interval == interval || state.interval == Interval::Red)
+                    && state.remaining == 0
+            })
+        };
+        let all_red = rings
+            .clone()
+            .all(|ring| self.rings[ring].interval == Interval::Red);
+
+        if !all_red && at_barrier(Interval::Green) {
+            for ring in 0..self.ring_count() {
+                if self.rings[ring].interval == Interval::Green {
+                    self.start_yellow(ring, intensities);
+                }
+            }
+            changed = true;
+        } else if at_barrier(Interval::Red) {
+            let next = (self.group + 1) % self.intersection.groups();
+            // With nobody waiting anywhere the intersection rests in red
+            // until a call comes in, rather than skipping round the groups.
+            let idle = !(0..self.intersection.phases.len()).any(|phase| self.has_demand(phase));
+            if next == 0 || idle {
+                if next == 0
+                    && let Some(plan) = self.next_plan.take()
+                {
+                    self.plan = plan;
+                }
+                // Flash starts from the red at the end of the cycle.
+                if let Some(cause) = self.flash_request.take() {
+                    self.flash = Some(cause);
+                    return false;
+                }
+            }
+            if idle {
+                return changed;
+            }
+            self.start_group(next, intensities);
+            changed = true;
+        }
+
+        changed
+    }
+
+    fn advance_preempt(&mut self, mut preempt: Preempt, intensities: &[u8]) -> bool {
+        let mut changed = false;
+
+        // Every green but the one being held runs out through its yellow
+        // and all-red, and then waits in red.
+        for ring in 0..self.ring_count() {
+            if self.rings[ring].remaining > 0 {
+                continue;
+            }
+            let index = self.phase_index(ring, self.rings[ring].position);
+            let held = index == preempt.dwell && preempt.stage != PreemptStage::Exit;
+            match self.rings[ring].interval {
+                Interval::Green if !held => self.start_yellow(ring, intensities),
+                Interval::Yellow => {
+                    self.rings[ring].interval = Interval::AllRed;
+                    self.rings[ring].remaining = self.plan.all_red;
+                }
+                Interval::AllRed => self.rings[ring].interval = Interval::Red,
+                _ => continue,
+            }
+            changed = true;
+        }
+
+        if self.locate(preempt.dwell).is_none() {
+            self.preempt = None;
+            return true;
+        }
+        let cleared = (0..self.ring_count()).all(|ring| {
+            let state = &self.rings[ring];
+            let index = self.phase_index(ring, state.position);
+            state.interval == Interval::Red
+                || (state.interval == Interval::Green && index == preempt.dwell)
+        });
+
+        match preempt.stage {
+            PreemptStage::Clearing if cleared && preempt.called => {
+                self.serve_only(preempt.dwell, 0);
+                preempt.stage = PreemptStage::Dwell;
+                changed = true;
+            }
+            PreemptStage::Clearing | PreemptStage::Dwell if !preempt.called => {
+                preempt.stage = PreemptStage::Exit;
+                changed = true;
+            }
+            PreemptStage::Exit
+                if (0..self.ring_count())
+                    .all(|ring| self.rings[ring].interval == Interval::Red) =>
+            {
+                self.reenter(preempt.reentry, intensities);
+                self.preempt = None;
+                return true;
+            }
+            _ => {}
+        }
+
+        self.preempt = Some(preempt);
+        changed
+    }
+
+    fn advance_rail(&mut self, mut rail: Rail<'a>, intensities: &[u8]) -> bool {
+        let config = rail.config;
+        let mut changed = false;
+
+        for ring in 0..self.ring_count() {
+            if self.rings[ring].remaining > 0 {
+                continue;
+            }
+            let index = self.phase_index(ring, self.rings[ring].position);
+            let held = match rail.stage {
+                RailStage::Clearing => index == config.track_clearance,
+                RailStage::Hold => config.hold.len() == 1,
+                _ => false,
+            };
+            match self.rings[ring].interval {
+                Interval::Green if !held => self.start_yellow(ring, intensities),
+                Interval::Yellow => {
+                    self.rings[ring].interval = Interval::AllRed;
+                    self.rings[ring].remaining = self.plan.all_red;
+                }
+                Interval::AllRed => self.rings[ring].interval = Interval::Red,
+                _ => continue,
+            }
+            changed = true;
+        }
+
+        let all_red = (0..self.ring_count()).all(|ring| self.rings[ring].interval == Interval::Red);
+        let cleared = (0..self.ring_count()).all(|ring| {
+            let state = &self.rings[ring];
+            let index = self.phase_index(ring, state.position);
+            state.interval == Interval::Red
+                || (state.interval == Interval::Green && index == config.track_clearance)
+        });
+
+        match rail.stage {
+            // The clearance green runs in full even if it was already
+            // showing.
+            RailStage::Clearing if cleared => {
+                self.serve_only(config.track_clearance, config.clearance_green);
+                rail.stage = RailStage::TrackClearance;
+                changed = true;
+            }
+            RailStage::Hold if !rail.train => {
+                rail.stage = RailStage::Exit;
+                changed = true;
+            }
+            RailStage::TrackClearance | RailStage::Hold | RailStage::Exit if all_red => {
+                if !rail.train {
+                    self.reenter(config.reentry, intensities);
+                    self.rail = None;
+                    return true;
+                }
+                if !config.hold.is_empty() {
+                    if rail.stage == RailStage::Hold {
+                        rail.hold = (rail.hold + 1) % config.hold.len();
+                    } else {
+                        rail.hold = 0;
+                    }
+                    let phase = config.hold[rail.hold];
+                    self.serve_only(phase, self.green_seconds(phase, intensities));
+                    changed = true;
+                }
+                changed |= rail.stage != RailStage::Hold;
+                rail.stage = RailStage::Hold;
+            }
+            _ => {}
+        }
+
+        self.rail = Some(rail);
+        changed
+    }
+
+    /// Resumes the normal sequence at `phase`, which is called so that it
+    /// is served even with no vehicle waiting on it.
+    fn reenter(&mut self, phase: usize, intensities: &[u8]) {
+        let (ring, group, position) = self.locate(phase).unwrap_or((0, 0, 0));
+        self.group = group;
+        let phase = self.phase_index(ring, position);
+        self.demand |= self.intersection.phases[phase].approaches();
+        for other in 0..self.ring_count() {
+            self.rings[other].position = if other == ring { position } else { 0 };
+            self.start_green(other, intensities);
+        }
+    }
+
+    fn show(&self, lamps: &mut impl LampOutput) {
+        for (index, approach) in self.intersection.approaches.iter().enumerate() {
+            approach
+                .through
+                .show(lamps, self.signal(Movement::Through(index)));
+            if let Some(turn) = &approach.turn {
+                turn.show(lamps, self.signal(Movement::Turn(index)));
+            }
+        }
+        for (crossing, config) in self.intersection.crossings.iter().enumerate() {
+            config.head.show(lamps, self.ped_signal(crossing));
+        }
+    }
+
+    fn show_flash(&self, lamps: &mut impl LampOutput) {
+        for approach in self.intersection.approaches {
+            approach.through.flash(lamps, approach.flash);
+            if let Some(turn) = &approach.turn {
+                turn.flash(lamps, Signal::Red);
+            }
+        }
+        // Pedestrian signals go dark.
+        for crossing in self.intersection.crossings {
+            lamps.set_lamp(crossing.head.walk, false);
+            lamps.set_lamp(crossing.head.dont_walk, false);
+        }
+    }
+
+    fn next_event(&self) -> u16 {
+        let rings = self.rings[..self.ring_count()]
+            .iter()
+            .map(|ring| ring.remaining);
+        let peds = self.peds.iter().map(|ped| ped.remaining);
+        rings
+            .chain(peds)
+            .filter(|&remaining| remaining > 0)
+            .min()
+            .unwrap_or(0)
+    }
+}
@@ -0,0 +1,110 @@
+use crate::intensity::{HIGH_INTENSE, INTENSE, NORMAL};
+
+/// Bins a detector's sliding window is kept in. The window covers the bins
+/// before the current one plus whatever the current one holds so far.
+pub const WINDOW_BINS: usize = 30;
+
+/// Traffic from which an approach counts as [`INTENSE`] or
+/// [`HIGH_INTENSE`]. Either the occupancy or the volume reaching its
+/// threshold is enough.
+#[derive(Clone, Copy)]
+pub struct Thresholds {
+    /// Percent of the window the detector was occupied.
+    pub intense_occupancy: u8,
+    pub high_intense_occupancy: u8,
+    /// Vehicles counted over the window.
+    pub intense_volume: u16,
+    pub high_intense_volume: u16,
+}
+
+/// Loop or presence detector on one lane.
+///
+/// Vehicles are counted through [`Detector::pulse`]; occupancy comes from
+/// sampling the input at a steady rate through [`Detector::sample`]. Both
+/// are kept over a sliding window of [`WINDOW_BINS`] bins of
+/// `samples_per_bin` samples each.
+#[derive(Clone, Copy)]
+pub struct Detector {
+    samples_per_bin: u16,
+    /// Samples taken into the current bin.
+    samples: u16,
+    bin: usize,
+    /// Completed bins still in the window.
+    filled: usize,
+    occupied: [u16; WINDOW_BINS],
+    pulses: [u16; WINDOW_BINS],
+    count: u32,
+}
+
+impl Detector {
+    pub const fn new(samples_per_bin: u16) -> Self {
+        Detector {
+            samples_per_bin,
+            samples: 0,
+            bin: 0,
+            filled: 0,
+            occupied: [0; WINDOW_BINS],
+            pulses: [0; WINDOW_BINS],
+            count: 0,
+        }
+    }
+
+    /// Counts one vehicle.
+    pub fn pulse(&mut self) {
+        self.pulses[self.bin] = self.pulses[self.bin].saturating_add(1);
+        self.count = self.count.wrapping_add(1);
+    }
+
+    /// Records one sample of the input, moving the window on once the
+    /// current bin is full.
+    pub fn sample(&mut self, present: bool) {
+        if present {
+            self.occupied[self.bin] += 1;
+        }
+        self.samples += 1;
+        if self.samples >= self.samples_per_bin {
+            self.bin = (self.bin + 1) % WINDOW_BINS;
+            self.occupied[self.bin] = 0;
+            self.pulses[self.bin] = 0;
+            self.samples = 0;
+            self.filled = (self.filled + 1).min(WINDOW_BINS - 1);
+        }
+    }
+
+    /// Vehicles counted since start-up.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Vehicles counted over the window.
+    pub fn volume(&self) -> u16 {
+        self.pulses
+            .iter()
+            .fold(0, |total: u16, &pulses| total.saturating_add(pulses))
+    }
+
+    /// Percent of the window's samples that saw a vehicle.
+    pub fn occupancy(&self) -> u8 {
+        let samples = self.filled as u32 * self.samples_per_bin as u32 + self.samples as u32;
+        if samples == 0 {
+            return 0;
+        }
+        let occupied: u32 = self.occupied.iter().map(|&bin| bin as u32).sum();
+        (occupied * 100 / samples) as u8
+    }
+
+    /// Intensity level the recent traffic calls for.
+    pub fn level(&self, thresholds: &Thresholds) -> u8 {
+        let occupancy = self.occupancy();
+        let volume = self.volume();
+        if occupancy >= thresholds.high_intense_occupancy
+            || volume >= thresholds.high_intense_volume
+        {
+            HIGH_INTENSE
+        } else if occupancy >= thresholds.intense_occupancy || volume >= thresholds.intense_volume {
+            INTENSE
+        } else {
+            NORMAL
+        }
+    }
+}
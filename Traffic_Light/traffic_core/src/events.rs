@@ -0,0 +1,63 @@
+use crate::schedule::TimeOfDay;
+
+/// Entries kept by an [`EventLog`]; the oldest is overwritten first.
+pub const LOG_LEN: usize = 32;
+
+/// Something worth keeping a record of, naming approaches by index.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Event {
+    /// The controller took up an emergency vehicle's preemption call on
+    /// the approach.
+    PreemptCalled(usize),
+    /// The approach was given its preemption green.
+    PreemptDwell(usize),
+    /// Preemption ended and the normal sequence resumed.
+    PreemptEnded(usize),
+    /// The railroad gate input reported a train.
+    RailPreemptCalled,
+    /// Railroad preemption ended and the normal sequence resumed.
+    RailPreemptEnded,
+    /// The HSE failed, at start-up or while running, and the clocks moved
+    /// to the HSI.
+    HseFailed,
+    /// The LSE did not start, and the RTC runs from the LSI.
+    LseFailed,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LogEntry {
+    pub time: TimeOfDay,
+    pub event: Event,
+}
+
+/// The most recent [`LOG_LEN`] events with the time they happened.
+pub struct EventLog {
+    entries: [Option<LogEntry>; LOG_LEN],
+    next: usize,
+}
+
+impl EventLog {
+    pub const fn new() -> Self {
+        EventLog {
+            entries: [None; LOG_LEN],
+            next: 0,
+        }
+    }
+
+    pub fn record(&mut self, time: TimeOfDay, event: Event) {
+        self.entries[self.next] = Some(LogEntry { time, event });
+        self.next = (self.next + 1) % LOG_LEN;
+    }
+
+    /// Entries from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = &LogEntry> {
+        let (newer, older) = self.entries.split_at(self.next);
+        older.iter().chain(newer).flatten()
+    }
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,10 @@
+/// Switches individual lamps on or off.
+///
+/// Lamps are identified by the number the board uses for them, which for
+/// the STM32 firmware is the GPIOA pin.
+pub trait LampOutput {
+    fn set_lamp(&mut self, lamp: u16, on: bool);
+
+    /// Leaves the lamp flashing until the next `set_lamp` for it.
+    fn flash_lamp(&mut self, lamp: u16);
+}
@@ -0,0 +1,22 @@
+use crate::blink::{BLINK_FAST, BLINK_MEDIUM, BLINK_OFF};
+
+// Traffic intensity levels
+pub const NORMAL: u8 = 0;
+pub const INTENSE: u8 = 1;
+pub const HIGH_INTENSE: u8 = 2;
+
+const LEVELS: u8 = 3;
+
+/// Level selected by one press of the intensity button.
+pub fn next_level(level: u8) -> u8 {
+    (level + 1) % LEVELS
+}
+
+/// Indicator blink rate shown for an intensity level.
+pub fn indicator_rate(level: u8) -> u8 {
+    match level {
+        INTENSE => BLINK_MEDIUM,
+        HIGH_INTENSE => BLINK_FAST,
+        _ => BLINK_OFF,
+    }
+}
@@ -0,0 +1,23 @@
+#![no_std]
+
+//! Hardware-independent traffic light logic.
+//!
+//! Timing, intensity and blink-rate handling live here so they can be
+//! tested on the host. The firmware reaches the board only through the
+//! traits in [`hal`].
+
+pub mod blink;
+pub mod controller;
+pub mod detector;
+pub mod events;
+pub mod hal;
+pub mod intensity;
+pub mod monitor;
+pub mod pedestrian;
+pub mod phase;
+pub mod schedule;
+pub mod time;
+pub mod timing;
+
+pub use controller::Controller;
+pub use hal::LampOutput;
@@ -0,0 +1,105 @@
+use crate::hal::LampOutput;
+use crate::phase::SignalHead;
+
+/// Most heads one monitor watches; compatibility rows are bit masks.
+pub const MAX_HEADS: usize = 16;
+
+/// Consecutive checks a fault must persist before the monitor trips.
+pub const TRIP_CHECKS: u8 = 2;
+
+/// Why the monitor tripped, naming heads by their index.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Fault {
+    /// Two conflicting heads were green at once.
+    ConflictingGreens(usize, usize),
+    /// Neither of two conflicting heads showed red.
+    MissingRed(usize, usize),
+    /// A head had no lamp lit.
+    Dark(usize),
+}
+
+/// Watches the lamps actually lit, independently of the controller, and
+/// latches the first conflict it sees.
+pub struct ConflictMonitor<'a> {
+    heads: &'a [SignalHead],
+    /// Bit `j` of row `i` is set when heads `i` and `j` may both show
+    /// something other than red.
+    compatible: &'a [u16],
+    suspect: Option<Fault>,
+    checks: u8,
+    fault: Option<Fault>,
+}
+
+impl<'a> ConflictMonitor<'a> {
+    pub const fn new(heads: &'a [SignalHead], compatible: &'a [u16]) -> Self {
+        ConflictMonitor {
+            heads,
+            compatible,
+            suspect: None,
+            checks: 0,
+            fault: None,
+        }
+    }
+
+    /// Latched fault, if the monitor has tripped.
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    /// Checks one readback of the lamp outputs, a bit per lamp. Returns the
+    /// latched fault once the monitor has tripped.
+    pub fn check(&mut self, lit: u32) -> Option<Fault> {
+        if self.fault.is_some() {
+            return self.fault;
+        }
+
+        match self.scan(lit) {
+            Some(fault) if self.suspect == Some(fault) => self.checks += 1,
+            Some(fault) => {
+                self.suspect = Some(fault);
+                self.checks = 1;
+            }
+            None => {
+                self.suspect = None;
+                self.checks = 0;
+            }
+        }
+
+        if self.checks >= TRIP_CHECKS {
+            self.fault = self.suspect;
+        }
+        self.fault
+    }
+
+    /// Drives the all-way flashing red the intersection falls back to.
+    pub fn fail_safe(&self, lamps: &mut impl LampOutput) {
+        for head in self.heads {
+            lamps.set_lamp(head.green, false);
+            lamps.set_lamp(head.yellow, false);
+            lamps.flash_lamp(head.red);
+        }
+    }
+
+    fn scan(&self, lit: u32) -> Option<Fault> {
+        let on = |lamp: u16| lit & (1 << lamp) != 0;
+
+        for (i, head) in self.heads.iter().enumerate() {
+            for (j, other) in self.heads.iter().enumerate().skip(i + 1) {
+                if self.compatible[i] & (1 << j) != 0 {
+                    continue;
+                }
+                if on(head.green) && on(other.green) {
+                    return Some(Fault::ConflictingGreens(i, j));
+                }
+                if !on(head.red) && !on(other.red) {
+                    return Some(Fault::MissingRed(i, j));
+                }
+            }
+        }
+
+        self.heads
+            .iter()
+            .position(|head| !on(head.red) && !on(head.yellow) && !on(head.green))
+            .map(Fault::Dark)
+    }
+}
@@ -0,0 +1,126 @@
+use crate::hal::LampOutput;
+
+/// Most crossings one intersection can have; calls are kept as a bit mask.
+pub const MAX_CROSSINGS: usize = 8;
+
+/// WALK and DON'T WALK lamps of one pedestrian signal.
+#[derive(Clone, Copy)]
+pub struct PedHead {
+    pub walk: u16,
+    pub dont_walk: u16,
+}
+
+/// Lengths in seconds of the pedestrian intervals.
+#[derive(Clone, Copy)]
+pub struct PedTiming {
+    /// Steady WALK.
+    pub walk: u16,
+    /// Flashing DON'T WALK while people finish crossing.
+    pub clearance: u16,
+    /// Steady DON'T WALK before the vehicle yellow.
+    pub buffer: u16,
+}
+
+impl PedTiming {
+    /// Green a phase needs to fit the whole pedestrian sequence.
+    pub fn total(&self) -> u16 {
+        self.walk + self.clearance + self.buffer
+    }
+}
+
+/// A crosswalk with its push button, served alongside one phase.
+#[derive(Clone, Copy)]
+pub struct Crossing {
+    pub head: PedHead,
+    /// Input pin of the push button.
+    pub button: u16,
+    /// Phase whose green the crossing runs with.
+    pub phase: usize,
+    pub timing: PedTiming,
+}
+
+/// Indication shown by a pedestrian signal.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PedSignal {
+    Walk,
+    FlashingDontWalk,
+    DontWalk,
+}
+
+impl PedHead {
+    pub fn show(&self, lamps: &mut impl LampOutput, signal: PedSignal) {
+        lamps.set_lamp(self.walk, signal == PedSignal::Walk);
+        match signal {
+            PedSignal::FlashingDontWalk => lamps.flash_lamp(self.dont_walk),
+            _ => lamps.set_lamp(self.dont_walk, signal == PedSignal::DontWalk),
+        }
+    }
+}
+
+/// Pedestrian interval a crossing is timing.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PedInterval {
+    DontWalk,
+    Walk,
+    Clearance,
+    Buffer,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct PedState {
+    pub(crate) interval: PedInterval,
+    pub(crate) remaining: u16,
+}
+
+impl PedState {
+    pub(crate) const fn new() -> Self {
+        PedState {
+            interval: PedInterval::DontWalk,
+            remaining: 0,
+        }
+    }
+
+    pub(crate) fn signal(&self) -> PedSignal {
+        match self.interval {
+            PedInterval::Walk => PedSignal::Walk,
+            PedInterval::Clearance => PedSignal::FlashingDontWalk,
+            PedInterval::DontWalk | PedInterval::Buffer => PedSignal::DontWalk,
+        }
+    }
+
+    pub(crate) fn start(&mut self, timing: &PedTiming) {
+        self.interval = PedInterval::Walk;
+        self.remaining = timing.walk;
+    }
+
+    /// Cuts a WALK short for preemption. The clearance still runs in full
+    /// so nobody is left in the road; returns the seconds until it ends.
+    pub(crate) fn end_walk(&mut self, timing: &PedTiming) -> u16 {
+        match self.interval {
+            PedInterval::Walk => {
+                self.interval = PedInterval::Clearance;
+                self.remaining = timing.clearance;
+                timing.clearance
+            }
+            PedInterval::Clearance => self.remaining,
+            PedInterval::Buffer | PedInterval::DontWalk => 0,
+        }
+    }
+
+    /// Moves to the next interval once the current one has run out.
+    /// Returns whether anything changed.
+    pub(crate) fn advance(&mut self, timing: &PedTiming) -> bool {
+        if self.remaining > 0 {
+            return false;
+        }
+        let (interval, remaining) = match self.interval {
+            PedInterval::Walk => (PedInterval::Clearance, timing.clearance),
+            PedInterval::Clearance => (PedInterval::Buffer, timing.buffer),
+            PedInterval::Buffer => (PedInterval::DontWalk, 0),
+            PedInterval::DontWalk => return false,
+        };
+        self.interval = interval;
+        self.remaining = remaining;
+        true
+    }
+}
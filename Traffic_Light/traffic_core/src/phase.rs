@@ -0,0 +1,209 @@
+use crate::hal::LampOutput;
+use crate::intensity::{HIGH_INTENSE, INTENSE, NORMAL};
+use crate::pedestrian::Crossing;
+use crate::timing::{ApproachTiming, TimingPlan};
+
+/// Most approaches an intersection can have. Sets of approaches, such as
+/// detector demand, are kept one bit each in a `u32`.
+pub const MAX_APPROACHES: usize = 32;
+
+/// Indication shown by one signal head.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Signal {
+    Red,
+    Yellow,
+    Green,
+}
+
+/// Red, yellow and green lamps of one signal head.
+#[derive(Clone, Copy)]
+pub struct SignalHead {
+    pub red: u16,
+    pub yellow: u16,
+    pub green: u16,
+}
+
+impl SignalHead {
+    pub fn lamp(&self, signal: Signal) -> u16 {
+        match signal {
+            Signal::Red => self.red,
+            Signal::Yellow => self.yellow,
+            Signal::Green => self.green,
+        }
+    }
+
+    /// Flashes the lamp for `signal` and switches the others off.
+    pub fn flash(&self, lamps: &mut impl LampOutput, signal: Signal) {
+        let lit = self.lamp(signal);
+        lamps.flash_lamp(lit);
+        for lamp in [self.red, self.yellow, self.green] {
+            if lamp != lit {
+                lamps.set_lamp(lamp, false);
+            }
+        }
+    }
+
+    /// Lights the lamp for `signal` before switching the others off, so a
+    /// conflict monitor never sees the head dark.
+    pub fn show(&self, lamps: &mut impl LampOutput, signal: Signal) {
+        let lit = self.lamp(signal);
+        lamps.set_lamp(lit, true);
+        for lamp in [self.red, self.yellow, self.green] {
+            if lamp != lit {
+                lamps.set_lamp(lamp, false);
+            }
+        }
+    }
+}
+
+/// One leg of the intersection.
+#[derive(Clone, Copy)]
+pub struct Approach {
+    pub through: SignalHead,
+    /// Protected turn arrow, if the approach has one.
+    pub turn: Option<SignalHead>,
+    /// Input pin of the intensity button or detector for this approach.
+    pub intensity: u16,
+    /// Detector timing, for approaches served by actuated phases.
+    pub timing: Option<ApproachTiming>,
+    /// Colour the through head flashes in flash mode: yellow on the main
+    /// road, red on side streets. Turn arrows always flash red.
+    pub flash: Signal,
+}
+
+impl Approach {
+    pub fn heads(&self) -> impl Iterator<Item = &SignalHead> {
+        core::iter::once(&self.through).chain(self.turn.as_ref())
+    }
+
+    pub fn head(&self, movement: Movement) -> Option<&SignalHead> {
+        match movement {
+            Movement::Through(_) => Some(&self.through),
+            Movement::Turn(_) => self.turn.as_ref(),
+        }
+    }
+}
+
+/// A stream of traffic, named by the index of the approach it leaves from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Movement {
+    Through(usize),
+    Turn(usize),
+}
+
+impl Movement {
+    pub fn approach(&self) -> usize {
+        match *self {
+            Movement::Through(approach) | Movement::Turn(approach) => approach,
+        }
+    }
+}
+
+/// Where an interval takes its length from.
+#[derive(Clone, Copy)]
+pub enum PhaseDuration {
+    /// Index into the timing plan's delays for the intensities of
+    /// approaches 0 (left) and 1 (right).
+    Delay(usize),
+    /// Length for [`NORMAL`], [`INTENSE`] and [`HIGH_INTENSE`], picked by
+    /// the busiest approach the phase serves.
+    Intensity([u16; 3]),
+    /// Fixed length in seconds.
+    Fixed(u16),
+    /// Green timed by detectors from the [`ApproachTiming`] of the
+    /// approaches served. The phase is skipped when nobody is waiting.
+    /// Only meaningful for the green; it has no length of its own.
+    Actuated,
+}
+
+/// A set of movements that are green together, with its green and yellow
+/// lengths.
+#[derive(Clone, Copy)]
+pub struct Phase<'a> {
+    pub movements: &'a [Movement],
+    pub green: PhaseDuration,
+    pub yellow: PhaseDuration,
+}
+
+impl Phase<'_> {
+    pub fn serves(&self, movement: Movement) -> bool {
+        self.movements.contains(&movement)
+    }
+
+    /// Approaches this phase serves, one bit each. Panics on an approach
+    /// index of [`MAX_APPROACHES`] or more, which has no bit.
+    pub fn approaches(&self) -> u32 {
+        self.movements.iter().fold(0, |mask, movement| {
+            let approach = movement.approach();
+            assert!(approach < MAX_APPROACHES, "approach {approach} has no bit");
+            mask | 1 << approach
+        })
+    }
+
+    /// Combined detector timing of the approaches served, if the green is
+    /// [`PhaseDuration::Actuated`].
+    pub fn actuated(&self, approaches: &[Approach]) -> Option<ApproachTiming> {
+        if !matches!(self.green, PhaseDuration::Actuated) {
+            return None;
+        }
+        self.movements
+            .iter()
+            .filter_map(|movement| approaches.get(movement.approach())?.timing)
+            .reduce(ApproachTiming::max)
+    }
+
+    /// Highest intensity level among the approaches this phase serves.
+    pub fn intensity(&self, intensities: &[u8]) -> u8 {
+        self.movements
+            .iter()
+            .filter_map(|movement| intensities.get(movement.approach()).copied())
+            .filter(|&level| level <= HIGH_INTENSE)
+            .max()
+            .unwrap_or(NORMAL)
+    }
+
+    /// Length in seconds of `duration` for this phase under `plan` at the
+    /// given intensities.
+    pub fn seconds(&self, duration: PhaseDuration, plan: &TimingPlan, intensities: &[u8]) -> u16 {
+        match duration {
+            PhaseDuration::Delay(index) => {
+                let level = |approach| intensities.get(approach).copied().unwrap_or(NORMAL);
+                plan.delays(level(0), level(1))[index]
+            }
+            PhaseDuration::Intensity(lengths) => match self.intensity(intensities) {
+                INTENSE => lengths[1],
+                HIGH_INTENSE => lengths[2],
+                _ => lengths[0],
+            },
+            PhaseDuration::Fixed(seconds) => seconds,
+            PhaseDuration::Actuated => 0,
+        }
+    }
+}
+
+/// Phase sequence of one ring, split into barrier groups.
+///
+/// Every ring of an intersection has the same number of groups. Phases in
+/// the same group of different rings run concurrently, so they must not
+/// conflict; all rings cross each barrier together.
+#[derive(Clone, Copy)]
+pub struct Ring<'a> {
+    pub groups: &'a [&'a [usize]],
+}
+
+/// Everything that describes one site: its approaches, phases, the rings
+/// and barriers that order them, and its pedestrian crossings. A site has
+/// at most [`MAX_APPROACHES`] approaches.
+#[derive(Clone, Copy)]
+pub struct Intersection<'a> {
+    pub approaches: &'a [Approach],
+    pub phases: &'a [Phase<'a>],
+    pub rings: &'a [Ring<'a>],
+    pub crossings: &'a [Crossing],
+}
+
+impl Intersection<'_> {
+    pub fn groups(&self) -> usize {
+        self.rings.first().map_or(0, |ring| ring.groups.len())
+    }
+}
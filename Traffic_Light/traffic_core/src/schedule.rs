@@ -0,0 +1,135 @@
+use crate::timing::TimingPlan;
+
+/// Time of day on the 24-hour clock.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct TimeOfDay {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl TimeOfDay {
+    pub const fn new(hour: u8, minute: u8, second: u8) -> Self {
+        TimeOfDay {
+            hour,
+            minute,
+            second,
+        }
+    }
+}
+
+/// Day of the week, numbered from Monday as the RTC's WDU field counts.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Weekday {
+    Monday = 1,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    const ALL: [Weekday; 7] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ];
+
+    /// Day numbered 1 (Monday) to 7 (Sunday), or `None` for anything else,
+    /// such as the RTC's unset 0.
+    pub const fn from_number(number: u8) -> Option<Self> {
+        match number {
+            1..=7 => Some(Self::ALL[number as usize - 1]),
+            _ => None,
+        }
+    }
+
+    /// The day `days` days before this one.
+    pub const fn before(self, days: u8) -> Self {
+        Self::ALL[(self as usize + 6 - days as usize % 7) % 7]
+    }
+}
+
+/// A set of days of the week, one bit per day.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Days(u8);
+
+impl Days {
+    pub const EVERY_DAY: Days = Days(0b111_1111);
+    pub const WEEKDAYS: Days = Days(0b001_1111);
+    pub const WEEKEND: Days = Days(0b110_0000);
+
+    pub const fn only(day: Weekday) -> Self {
+        Days(1 << (day as u8 - 1))
+    }
+
+    pub const fn contains(self, day: Weekday) -> bool {
+        self.0 & Self::only(day).0 != 0
+    }
+}
+
+/// Day of the week and time of day, as the RTC keeps them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WeekTime {
+    pub day: Weekday,
+    pub time: TimeOfDay,
+}
+
+impl WeekTime {
+    pub const fn new(day: Weekday, time: TimeOfDay) -> Self {
+        WeekTime { day, time }
+    }
+}
+
+/// A timing plan, the days it runs on and the time of day it takes over.
+#[derive(Clone, Copy)]
+pub struct ScheduleEntry<'a> {
+    pub days: Days,
+    pub start: TimeOfDay,
+    pub plan: &'a TimingPlan,
+    /// Flash the intersection instead of cycling it.
+    pub flash: bool,
+}
+
+/// Timing plans by day of the week and time of day.
+///
+/// Entries are in order of start time. Each runs until the next one
+/// starts on a day it covers, so the last of a day runs on past midnight
+/// until the first entry of the following day.
+#[derive(Clone, Copy)]
+pub struct Schedule<'a> {
+    pub entries: &'a [ScheduleEntry<'a>],
+}
+
+impl<'a> Schedule<'a> {
+    /// Entry in force at `now`, or `None` if no entry runs on any day.
+    pub fn entry_at(&self, now: WeekTime) -> Option<&'a ScheduleEntry<'a>> {
+        let started = self
+            .entries
+            .iter()
+            .rev()
+            .find(|entry| entry.days.contains(now.day) && entry.start <= now.time);
+        // Otherwise the last entry of the latest earlier day that has one,
+        // up to this day a week ago.
+        started.or_else(|| {
+            (1..=7).find_map(|back| {
+                let day = now.day.before(back);
+                self.entries
+                    .iter()
+                    .rev()
+                    .find(|entry| entry.days.contains(day))
+            })
+        })
+    }
+
+    /// Plan due at `now`, or `None` if no entry runs on any day.
+    pub fn plan_at(&self, now: WeekTime) -> Option<&'a TimingPlan> {
+        self.entry_at(now).map(|entry| entry.plan)
+    }
+}
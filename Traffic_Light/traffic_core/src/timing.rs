@@ -0,0 +1,93 @@
+use crate::intensity::{HIGH_INTENSE, INTENSE, NORMAL};
+
+/// Interval lengths in seconds for one way of running the intersection.
+///
+/// The delay tables are [right green, right yellow, left green, left yellow]
+/// and are picked by the left and right intensity levels.
+#[derive(Clone, Copy)]
+pub struct TimingPlan {
+    pub normal: [u16; 4],
+    pub left_intense: [u16; 4],
+    pub left_high_intense: [u16; 4],
+    pub right_intense: [u16; 4],
+    pub right_high_intense: [u16; 4],
+    /// Red shown to every approach after each yellow.
+    pub all_red: u16,
+    /// Steady red shown to every approach when leaving flash, before the
+    /// first green.
+    pub startup_red: u16,
+}
+
+/// Actuated green timing for one approach, in seconds.
+///
+/// The green runs for at least `min_green`. After that every vehicle the
+/// detector sees keeps it going for another `passage`; once a whole passage
+/// goes by with no vehicle the phase gaps out. It maxes out after
+/// `max_green` however busy the approach is, as long as another phase is
+/// waiting.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ApproachTiming {
+    pub min_green: u16,
+    pub passage: u16,
+    pub max_green: u16,
+}
+
+impl ApproachTiming {
+    /// Timing that satisfies both `self` and `other`, for a phase serving
+    /// more than one approach.
+    pub fn max(self, other: ApproachTiming) -> ApproachTiming {
+        ApproachTiming {
+            min_green: self.min_green.max(other.min_green),
+            passage: self.passage.max(other.passage),
+            max_green: self.max_green.max(other.max_green),
+        }
+    }
+}
+
+pub const STANDARD_PLAN: TimingPlan = TimingPlan {
+    normal: [15, 5, 15, 5],
+    left_intense: [10, 5, 30, 5],
+    left_high_intense: [10, 5, 50, 5],
+    right_intense: [30, 5, 10, 5],
+    right_high_intense: [50, 5, 10, 5],
+    all_red: 2,
+    startup_red: 6,
+};
+
+impl TimingPlan {
+    pub fn delays(&self, left_intensity: u8, right_intensity: u8) -> [u16; 4] {
+        if left_intensity == right_intensity {
+            self.normal
+        } else if left_intensity == NORMAL {
+            if right_intensity == INTENSE {
+                self.right_intense
+            } else if right_intensity == HIGH_INTENSE {
+                self.right_high_intense
+            } else {
+                self.normal
+            }
+        } else if left_intensity == INTENSE {
+            if right_intensity == NORMAL {
+                self.left_intense
+            } else if right_intensity == HIGH_INTENSE {
+                self.right_intense
+            } else {
+                self.normal
+            }
+        } else if left_intensity == HIGH_INTENSE {
+            if right_intensity == NORMAL {
+                self.left_high_intense
+            } else if right_intensity == INTENSE {
+                self.left_intense
+            } else {
+                self.normal
+            }
+        } else {
+            self.normal
+        }
+    }
+}
+
+pub fn get_traffic_delays(left_intensity: u8, right_intensity: u8) -> [u16; 4] {
+    STANDARD_PLAN.delays(left_intensity, right_intensity)
+}
@@ -0,0 +1,252 @@
+mod common;
+
+use common::Lamps;
+use traffic_core::Controller;
+use traffic_core::controller::FlashCause;
+use traffic_core::intensity::NORMAL;
+use traffic_core::phase::{
+    Approach, Intersection, Movement, Phase, PhaseDuration, Ring, Signal, SignalHead,
+};
+use traffic_core::timing::{ApproachTiming, STANDARD_PLAN};
+
+const fn approach(first: u16, timing: ApproachTiming) -> Approach {
+    Approach {
+        through: SignalHead {
+            red: first,
+            yellow: first + 1,
+            green: first + 2,
+        },
+        turn: None,
+        intensity: 20 + first,
+        timing: Some(timing),
+        flash: Signal::Red,
+    }
+}
+
+// A main road and two side streets, each served by its own actuated phase.
+const MAIN: usize = 0;
+const SIDE: usize = 1;
+const ALLEY: usize = 2;
+const MAIN_TIMING: ApproachTiming = ApproachTiming {
+    min_green: 10,
+    passage: 3,
+    max_green: 20,
+};
+const SIDE_TIMING: ApproachTiming = ApproachTiming {
+    min_green: 5,
+    passage: 2,
+    max_green: 12,
+};
+const APPROACHES: [Approach; 3] = [
+    approach(0, MAIN_TIMING),
+    approach(3, SIDE_TIMING),
+    approach(6, SIDE_TIMING),
+];
+const PHASES: [Phase; 3] = [
+    Phase {
+        movements: &[Movement::Through(MAIN)],
+        green: PhaseDuration::Actuated,
+        yellow: PhaseDuration::Fixed(3),
+    },
+    Phase {
+        movements: &[Movement::Through(SIDE)],
+        green: PhaseDuration::Actuated,
+        yellow: PhaseDuration::Fixed(3),
+    },
+    Phase {
+        movements: &[Movement::Through(ALLEY)],
+        green: PhaseDuration::Actuated,
+        yellow: PhaseDuration::Fixed(3),
+    },
+];
+const RINGS: [Ring; 1] = [Ring {
+    groups: &[&[0], &[1], &[2]],
+}];
+const INTERSECTION: Intersection = Intersection {
+    approaches: &APPROACHES,
+    phases: &PHASES,
+    rings: &RINGS,
+    crossings: &[],
+};
+
+const LEVELS: [u8; 3] = [NORMAL; 3];
+
+fn green(controller: &Controller, approach: usize) -> bool {
+    controller.signal(Movement::Through(approach)) == Signal::Green
+}
+
+#[test]
+fn phase_takes_the_longest_timing_of_its_approaches() {
+    let phase = Phase {
+        movements: &[Movement::Through(MAIN), Movement::Through(SIDE)],
+        green: PhaseDuration::Actuated,
+        yellow: PhaseDuration::Fixed(3),
+    };
+    assert_eq!(phase.actuated(&APPROACHES), Some(MAIN_TIMING));
+    assert_eq!(PHASES[0].approaches(), 1 << MAIN);
+
+    let fixed = Phase {
+        green: PhaseDuration::Fixed(10),
+        ..phase
+    };
+    assert_eq!(fixed.actuated(&APPROACHES), None);
+}
+
+#[test]
+fn gaps_out_when_no_vehicle_follows_the_minimum_green() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    assert_eq!(controller.update(0, &LEVELS, &mut lamps), 10);
+    assert!(green(&controller, MAIN));
+
+    assert_eq!(controller.update(10, &LEVELS, &mut lamps), 3);
+    assert_eq!(controller.signal(Movement::Through(MAIN)), Signal::Yellow);
+}
+
+#[test]
+fn each_vehicle_extends_the_green_by_a_passage() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    controller.update(0, &LEVELS, &mut lamps);
+    controller.detect(1 << MAIN);
+    assert_eq!(controller.update(10, &LEVELS, &mut lamps), 3);
+    controller.detect(1 << MAIN);
+    assert_eq!(controller.update(3, &LEVELS, &mut lamps), 3);
+    assert!(green(&controller, MAIN));
+
+    // A whole passage with nobody on the detector.
+    assert_eq!(controller.update(3, &LEVELS, &mut lamps), 3);
+    assert!(!green(&controller, MAIN));
+}
+
+#[test]
+fn busy_green_maxes_out() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    let mut wait = controller.update(0, &LEVELS, &mut lamps);
+    let mut time = 0;
+    while green(&controller, MAIN) {
+        time += wait;
+        controller.detect(1 << MAIN);
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+    assert_eq!(time, MAIN_TIMING.max_green);
+}
+
+#[test]
+fn green_rests_while_nobody_else_waits() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    // The first cycle serves every approach once.
+    let mut wait = controller.update(0, &LEVELS, &mut lamps);
+    while !green(&controller, ALLEY) {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+    assert_eq!(controller.demand(), 0);
+
+    for _ in 0..20 {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+        assert!(green(&controller, ALLEY));
+    }
+    assert_eq!(wait, SIDE_TIMING.passage);
+}
+
+#[test]
+fn phase_without_demand_is_skipped() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    let mut wait = controller.update(0, &LEVELS, &mut lamps);
+    while !green(&controller, ALLEY) {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+
+    // A vehicle on red places a call; MAIN has none and is passed over.
+    controller.detect(1 << SIDE);
+    wait = controller.update(0, &LEVELS, &mut lamps);
+    assert_eq!(controller.demand(), 1 << SIDE);
+    while !green(&controller, SIDE) {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+        assert!(!green(&controller, MAIN));
+    }
+    assert_eq!(controller.demand(), 0);
+}
+
+#[test]
+fn rests_in_red_until_someone_arrives() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    let mut wait = controller.update(0, &LEVELS, &mut lamps);
+    while !green(&controller, ALLEY) {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+    // A cleared fault restarts the sequence with nobody waiting, so every
+    // phase would be skipped. Each update still has to come back.
+    controller.enter_flash(FlashCause::Fault);
+    controller.update(wait, &LEVELS, &mut lamps);
+    controller.leave_flash();
+    for _ in 0..20 {
+        wait = controller.update(wait.max(1), &LEVELS, &mut lamps);
+    }
+
+    controller.detect(1 << SIDE);
+    for _ in 0..20 {
+        if green(&controller, SIDE) {
+            return;
+        }
+        wait = controller.update(wait.max(1), &LEVELS, &mut lamps);
+    }
+    panic!("the call was never served");
+}
+
+// The main road on a timed green with only the side street actuated.
+const SEMI_PHASES: [Phase; 2] = [
+    Phase {
+        movements: &[Movement::Through(MAIN)],
+        green: PhaseDuration::Fixed(20),
+        yellow: PhaseDuration::Fixed(3),
+    },
+    PHASES[1],
+];
+const SEMI_RINGS: [Ring; 1] = [Ring {
+    groups: &[&[0], &[1]],
+}];
+const SEMI_ACTUATED: Intersection = Intersection {
+    approaches: &APPROACHES,
+    phases: &SEMI_PHASES,
+    rings: &SEMI_RINGS,
+    crossings: &[],
+};
+
+#[test]
+fn timed_main_road_rests_in_green_until_the_side_street_calls() {
+    let mut controller = Controller::new(&SEMI_ACTUATED, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    // Past the start-up cycle, which serves the side street once.
+    let mut wait = controller.update(0, &LEVELS, &mut lamps);
+    while !green(&controller, SIDE) {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+    while !green(&controller, MAIN) {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+    assert_eq!(wait, 20);
+
+    wait = controller.update(wait, &LEVELS, &mut lamps);
+    for _ in 0..20 {
+        assert_eq!(wait, 1);
+        assert!(green(&controller, MAIN));
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+
+    // A call ends the rest at the next check.
+    controller.detect(1 << SIDE);
+    controller.update(wait, &LEVELS, &mut lamps);
+    assert_eq!(controller.signal(Movement::Through(MAIN)), Signal::Yellow);
+}
@@ -0,0 +1,97 @@
+use traffic_core::blink::{
+    BLINK_FAST, BLINK_FLASH, BLINK_MEDIUM, BLINK_OFF, BLINK_SLOW, BLINK_TICK, Blinker,
+};
+use traffic_core::intensity::{HIGH_INTENSE, INTENSE, NORMAL, indicator_rate, next_level};
+use traffic_core::time::{Duration, Instant};
+
+/// Ticks at which the lamp toggled over `ticks` ticks, with the state it toggled to.
+fn toggles(blinker: &mut Blinker, ticks: usize) -> Vec<(usize, bool)> {
+    (0..ticks)
+        .filter_map(|tick| blinker.tick().map(|state| (tick, state)))
+        .collect()
+}
+
+#[test]
+fn button_cycles_through_all_levels() {
+    assert_eq!(next_level(NORMAL), INTENSE);
+    assert_eq!(next_level(INTENSE), HIGH_INTENSE);
+    assert_eq!(next_level(HIGH_INTENSE), NORMAL);
+}
+
+#[test]
+fn busier_levels_blink_faster() {
+    assert_eq!(indicator_rate(NORMAL), BLINK_OFF);
+    assert_eq!(indicator_rate(INTENSE), BLINK_MEDIUM);
+    assert_eq!(indicator_rate(HIGH_INTENSE), BLINK_FAST);
+}
+
+#[test]
+fn off_never_toggles() {
+    let mut blinker = Blinker::new();
+    assert!(toggles(&mut blinker, 24).is_empty());
+}
+
+#[test]
+fn fast_toggles_every_third_tick() {
+    let mut blinker = Blinker::new();
+    blinker.set_rate(BLINK_FAST);
+    assert_eq!(
+        toggles(&mut blinker, 12),
+        [(0, true), (3, false), (6, true), (9, false)]
+    );
+}
+
+#[test]
+fn slow_toggles_every_sixth_tick() {
+    let mut blinker = Blinker::new();
+    blinker.set_rate(BLINK_SLOW);
+    assert_eq!(
+        toggles(&mut blinker, 24),
+        [(0, true), (6, false), (12, true), (18, false)]
+    );
+}
+
+#[test]
+fn flash_is_lit_half_of_every_ten_ticks() {
+    let mut blinker = Blinker::with_rate(BLINK_FLASH);
+    assert_eq!(
+        toggles(&mut blinker, 20),
+        [(0, true), (5, false), (10, true), (15, false)]
+    );
+}
+
+#[test]
+fn medium_toggles_on_the_twelve_tick_cycle() {
+    let mut blinker = Blinker::new();
+    blinker.set_rate(BLINK_MEDIUM);
+    // The period of 8 restarts with the cycle, so the lamp is dark for 4.
+    assert_eq!(
+        toggles(&mut blinker, 24),
+        [(0, true), (8, false), (12, true), (20, false)]
+    );
+}
+
+#[test]
+fn switching_off_leaves_the_lamp_dark() {
+    let mut blinker = Blinker::new();
+    blinker.set_rate(BLINK_FAST);
+    assert_eq!(blinker.tick(), Some(true));
+
+    blinker.set_rate(BLINK_OFF);
+    assert_eq!(blinker.tick(), None);
+
+    blinker.set_rate(BLINK_FAST);
+    assert_eq!(toggles(&mut blinker, 6), [(2, true), (5, false)]);
+}
+
+#[test]
+fn update_runs_the_ticks_that_are_due() {
+    let mut blinker = Blinker::with_rate(BLINK_FLASH);
+    let start = Instant::from_micros(1_000_000);
+    assert_eq!(blinker.update(start), Some(true));
+    assert_eq!(blinker.update(start + Duration::from_millis(450)), None);
+
+    // A late call catches up on the toggle it missed.
+    assert_eq!(blinker.update(start + BLINK_TICK * 7), Some(false));
+    assert_eq!(blinker.update(start + Duration::from_secs(1)), Some(true));
+}
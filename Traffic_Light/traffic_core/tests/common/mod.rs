@@ -0,0 +1,36 @@
+// Shared by several test crates; each uses only part of it.
+#![allow(dead_code)]
+
+use traffic_core::LampOutput;
+
+/// Lamp states kept as a bit per lamp.
+#[derive(Default)]
+pub struct Lamps {
+    pub on: u32,
+    pub flashing: u32,
+}
+
+impl Lamps {
+    pub fn lit(&self, lamp: u16) -> bool {
+        self.on & (1 << lamp) != 0
+    }
+
+    pub fn flashing(&self, lamp: u16) -> bool {
+        self.flashing & (1 << lamp) != 0
+    }
+}
+
+impl LampOutput for Lamps {
+    fn set_lamp(&mut self, lamp: u16, on: bool) {
+        self.flashing &= !(1 << lamp);
+        if on {
+            self.on |= 1 << lamp;
+        } else {
+            self.on &= !(1 << lamp);
+        }
+    }
+
+    fn flash_lamp(&mut self, lamp: u16) {
+        self.flashing |= 1 << lamp;
+    }
+}
@@ -0,0 +1,308 @@
+mod common;
+
+use common::Lamps;
+use traffic_core::Controller;
+use traffic_core::intensity::{HIGH_INTENSE, INTENSE, NORMAL};
+use traffic_core::phase::{
+    Approach, Intersection, MAX_APPROACHES, Movement, Phase, PhaseDuration, Ring, Signal,
+    SignalHead,
+};
+use traffic_core::timing::STANDARD_PLAN;
+
+const fn head(first: u16) -> SignalHead {
+    SignalHead {
+        red: first,
+        yellow: first + 1,
+        green: first + 2,
+    }
+}
+
+// Left and right approaches, served one after the other.
+const TWO_WAY_APPROACHES: [Approach; 2] = [
+    Approach {
+        through: head(0),
+        turn: None,
+        intensity: 20,
+        timing: None,
+        flash: Signal::Red,
+    },
+    Approach {
+        through: head(3),
+        turn: None,
+        intensity: 21,
+        timing: None,
+        flash: Signal::Red,
+    },
+];
+const TWO_WAY_PHASES: [Phase; 2] = [
+    Phase {
+        movements: &[Movement::Through(1)],
+        green: PhaseDuration::Delay(0),
+        yellow: PhaseDuration::Delay(1),
+    },
+    Phase {
+        movements: &[Movement::Through(0)],
+        green: PhaseDuration::Delay(2),
+        yellow: PhaseDuration::Delay(3),
+    },
+];
+const TWO_WAY_RINGS: [Ring; 1] = [Ring {
+    groups: &[&[0], &[1]],
+}];
+const TWO_WAY: Intersection = Intersection {
+    approaches: &TWO_WAY_APPROACHES,
+    phases: &TWO_WAY_PHASES,
+    rings: &TWO_WAY_RINGS,
+    crossings: &[],
+};
+
+// North, south, east and west approaches, each with a protected left turn.
+const N: usize = 0;
+const S: usize = 1;
+const E: usize = 2;
+const W: usize = 3;
+const FOUR_WAY_APPROACHES: [Approach; 4] = [
+    Approach {
+        through: head(0),
+        turn: Some(head(3)),
+        intensity: 24,
+        timing: None,
+        flash: Signal::Red,
+    },
+    Approach {
+        through: head(6),
+        turn: Some(head(9)),
+        intensity: 25,
+        timing: None,
+        flash: Signal::Red,
+    },
+    Approach {
+        through: head(12),
+        turn: Some(head(15)),
+        intensity: 26,
+        timing: None,
+        flash: Signal::Red,
+    },
+    Approach {
+        through: head(18),
+        turn: Some(head(21)),
+        intensity: 27,
+        timing: None,
+        flash: Signal::Red,
+    },
+];
+const fn phase(movements: &'static [Movement], green: u16) -> Phase<'static> {
+    Phase {
+        movements,
+        green: PhaseDuration::Fixed(green),
+        yellow: PhaseDuration::Fixed(3),
+    }
+}
+const FOUR_WAY_PHASES: [Phase; 8] = [
+    phase(&[Movement::Turn(N)], 5),
+    phase(&[Movement::Through(S)], 20),
+    phase(&[Movement::Turn(S)], 10),
+    phase(&[Movement::Through(N)], 20),
+    phase(&[Movement::Turn(E)], 5),
+    phase(&[Movement::Through(W)], 15),
+    phase(&[Movement::Turn(W)], 5),
+    phase(&[Movement::Through(E)], 15),
+];
+const FOUR_WAY_RINGS: [Ring; 2] = [
+    Ring {
+        groups: &[&[0, 1], &[4, 5]],
+    },
+    Ring {
+        groups: &[&[2, 3], &[6, 7]],
+    },
+];
+const FOUR_WAY: Intersection = Intersection {
+    approaches: &FOUR_WAY_APPROACHES,
+    phases: &FOUR_WAY_PHASES,
+    rings: &FOUR_WAY_RINGS,
+    crossings: &[],
+};
+
+/// Runs the controller, returning the length of each interval it timed.
+fn run(
+    controller: &mut Controller,
+    intensities: &[u8],
+    lamps: &mut Lamps,
+    steps: usize,
+) -> Vec<u16> {
+    let mut waits = Vec::new();
+    let mut elapsed = 0;
+    for _ in 0..steps {
+        elapsed = controller.update(elapsed, intensities, lamps);
+        waits.push(elapsed);
+    }
+    waits
+}
+
+#[test]
+fn two_way_follows_the_traffic_delays() {
+    let mut controller = Controller::new(&TWO_WAY, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    assert_eq!(
+        run(&mut controller, &[NORMAL, HIGH_INTENSE], &mut lamps, 6),
+        [50, 5, 2, 10, 5, 2]
+    );
+}
+
+#[test]
+fn two_way_drives_both_heads() {
+    let mut controller = Controller::new(&TWO_WAY, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    controller.update(0, &[NORMAL, NORMAL], &mut lamps);
+    assert!(lamps.lit(5) && lamps.lit(0));
+    assert!(!lamps.lit(2) && !lamps.lit(3));
+
+    controller.update(15, &[NORMAL, NORMAL], &mut lamps);
+    assert!(lamps.lit(4) && lamps.lit(0));
+    assert!(!lamps.lit(5));
+
+    controller.update(5, &[NORMAL, NORMAL], &mut lamps);
+    assert!(lamps.lit(0) && lamps.lit(3));
+    assert!(!lamps.lit(4));
+
+    controller.update(2, &[NORMAL, NORMAL], &mut lamps);
+    assert!(lamps.lit(2) && lamps.lit(3));
+    assert!(!lamps.lit(0) && !lamps.lit(4));
+}
+
+#[test]
+fn compatible_movements_are_green_together() {
+    let mut controller = Controller::new(&FOUR_WAY, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    // Both lefts lead, then north turns off and the southbound through starts.
+    assert_eq!(controller.update(0, &[NORMAL; 4], &mut lamps), 5);
+    assert_eq!(controller.signal(Movement::Turn(N)), Signal::Green);
+    assert_eq!(controller.signal(Movement::Turn(S)), Signal::Green);
+
+    assert_eq!(controller.update(5, &[NORMAL; 4], &mut lamps), 3);
+    assert_eq!(controller.update(3, &[NORMAL; 4], &mut lamps), 2);
+    assert_eq!(controller.signal(Movement::Turn(N)), Signal::Red);
+    assert_eq!(controller.signal(Movement::Turn(S)), Signal::Green);
+
+    assert_eq!(controller.update(2, &[NORMAL; 4], &mut lamps), 3);
+    assert_eq!(controller.signal(Movement::Through(S)), Signal::Green);
+    assert_eq!(controller.signal(Movement::Turn(S)), Signal::Yellow);
+
+    assert_eq!(controller.update(3, &[NORMAL; 4], &mut lamps), 2);
+    controller.update(2, &[NORMAL; 4], &mut lamps);
+    assert_eq!(controller.signal(Movement::Through(N)), Signal::Green);
+    assert_eq!(controller.signal(Movement::Through(S)), Signal::Green);
+    for approach in [E, W] {
+        assert_eq!(controller.signal(Movement::Through(approach)), Signal::Red);
+        assert_eq!(controller.signal(Movement::Turn(approach)), Signal::Red);
+    }
+    assert!(lamps.lit(2) && lamps.lit(8) && lamps.lit(12) && lamps.lit(18));
+}
+
+#[test]
+fn rings_cross_the_barrier_together() {
+    let mut controller = Controller::new(&FOUR_WAY, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    // Ring 1 finishes its group at 30 s but holds green until ring 2 is done at 35 s.
+    let mut now = 0;
+    let mut wait = 0;
+    loop {
+        now += wait;
+        wait = controller.update(wait, &[NORMAL; 4], &mut lamps);
+        if controller.group() != 0 {
+            break;
+        }
+        if (10..35).contains(&now) {
+            assert_eq!(controller.signal(Movement::Through(S)), Signal::Green);
+        }
+    }
+    assert_eq!(now, 40);
+    assert_eq!(controller.signal(Movement::Turn(E)), Signal::Green);
+    assert_eq!(controller.signal(Movement::Through(N)), Signal::Red);
+}
+
+#[test]
+fn intensity_durations_follow_the_busiest_approach() {
+    let phase = Phase {
+        movements: &[Movement::Through(N), Movement::Through(S)],
+        green: PhaseDuration::Intensity([15, 30, 50]),
+        yellow: PhaseDuration::Fixed(3),
+    };
+
+    assert_eq!(
+        phase.seconds(
+            phase.green,
+            &STANDARD_PLAN,
+            &[NORMAL, NORMAL, HIGH_INTENSE, NORMAL]
+        ),
+        15
+    );
+    assert_eq!(
+        phase.seconds(
+            phase.green,
+            &STANDARD_PLAN,
+            &[NORMAL, INTENSE, HIGH_INTENSE, NORMAL]
+        ),
+        30
+    );
+    assert_eq!(
+        phase.seconds(
+            phase.green,
+            &STANDARD_PLAN,
+            &[HIGH_INTENSE, INTENSE, NORMAL, NORMAL]
+        ),
+        50
+    );
+}
+
+#[test]
+fn approaches_up_to_the_limit_have_a_bit_each() {
+    let phase = Phase {
+        movements: &[Movement::Through(0), Movement::Turn(MAX_APPROACHES - 1)],
+        green: PhaseDuration::Fixed(10),
+        yellow: PhaseDuration::Fixed(3),
+    };
+    assert_eq!(phase.approaches(), 1 | 1 << (MAX_APPROACHES - 1));
+}
+
+#[test]
+#[should_panic]
+fn approach_past_the_limit_is_rejected() {
+    let phase = Phase {
+        movements: &[Movement::Through(MAX_APPROACHES)],
+        green: PhaseDuration::Fixed(10),
+        yellow: PhaseDuration::Fixed(3),
+    };
+    phase.approaches();
+}
+
+#[test]
+#[should_panic]
+fn movement_must_name_an_approach_of_the_site() {
+    const PHASES: [Phase; 1] = [Phase {
+        movements: &[Movement::Through(2)],
+        green: PhaseDuration::Fixed(10),
+        yellow: PhaseDuration::Fixed(3),
+    }];
+    const RINGS: [Ring; 1] = [Ring { groups: &[&[0]] }];
+    const SITE: Intersection = Intersection {
+        phases: &PHASES,
+        rings: &RINGS,
+        ..TWO_WAY
+    };
+    Controller::new(&SITE, STANDARD_PLAN);
+}
+
+#[test]
+#[should_panic]
+fn site_needs_a_ring() {
+    const SITE: Intersection = Intersection {
+        rings: &[],
+        ..TWO_WAY
+    };
+    Controller::new(&SITE, STANDARD_PLAN);
+}
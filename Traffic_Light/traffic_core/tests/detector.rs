@@ -0,0 +1,65 @@
+use traffic_core::detector::{Detector, Thresholds, WINDOW_BINS};
+use traffic_core::intensity::{HIGH_INTENSE, INTENSE, NORMAL};
+
+const THRESHOLDS: Thresholds = Thresholds {
+    intense_occupancy: 20,
+    high_intense_occupancy: 40,
+    intense_volume: 10,
+    high_intense_volume: 20,
+};
+
+/// Samples `count` times with the loop occupied for the first `occupied`.
+fn run(detector: &mut Detector, count: usize, occupied: usize) {
+    for sample in 0..count {
+        detector.sample(sample < occupied);
+    }
+}
+
+#[test]
+fn idle_detector_reads_normal() {
+    let detector = Detector::new(10);
+    assert_eq!(detector.occupancy(), 0);
+    assert_eq!(detector.volume(), 0);
+    assert_eq!(detector.level(&THRESHOLDS), NORMAL);
+}
+
+#[test]
+fn occupancy_is_the_share_of_occupied_samples() {
+    let mut detector = Detector::new(10);
+    run(&mut detector, 40, 10);
+    assert_eq!(detector.occupancy(), 25);
+    assert_eq!(detector.level(&THRESHOLDS), INTENSE);
+
+    run(&mut detector, 40, 40);
+    assert_eq!(detector.occupancy(), 62);
+    assert_eq!(detector.level(&THRESHOLDS), HIGH_INTENSE);
+}
+
+#[test]
+fn pulses_set_the_volume_and_the_count() {
+    let mut detector = Detector::new(10);
+    for _ in 0..12 {
+        detector.pulse();
+        run(&mut detector, 5, 0);
+    }
+    assert_eq!(detector.count(), 12);
+    assert_eq!(detector.volume(), 12);
+    assert_eq!(detector.level(&THRESHOLDS), INTENSE);
+}
+
+#[test]
+fn old_traffic_slides_out_of_the_window() {
+    let mut detector = Detector::new(10);
+    for _ in 0..25 {
+        detector.pulse();
+    }
+    run(&mut detector, 100, 100);
+    assert_eq!(detector.level(&THRESHOLDS), HIGH_INTENSE);
+
+    // A full window of empty road.
+    run(&mut detector, 10 * WINDOW_BINS, 0);
+    assert_eq!(detector.occupancy(), 0);
+    assert_eq!(detector.volume(), 0);
+    assert_eq!(detector.count(), 25);
+    assert_eq!(detector.level(&THRESHOLDS), NORMAL);
+}
@@ -0,0 +1,39 @@
+use traffic_core::events::{Event, EventLog, LOG_LEN};
+use traffic_core::schedule::TimeOfDay;
+
+fn at(second: usize) -> TimeOfDay {
+    TimeOfDay::new(12, (second / 60) as u8, (second % 60) as u8)
+}
+
+#[test]
+fn entries_come_back_oldest_first() {
+    let mut log = EventLog::new();
+    assert_eq!(log.iter().count(), 0);
+
+    log.record(at(1), Event::PreemptCalled(1));
+    log.record(at(9), Event::PreemptDwell(1));
+    log.record(at(40), Event::PreemptEnded(1));
+
+    let events: Vec<_> = log.iter().map(|entry| (entry.time, entry.event)).collect();
+    assert_eq!(
+        events,
+        [
+            (at(1), Event::PreemptCalled(1)),
+            (at(9), Event::PreemptDwell(1)),
+            (at(40), Event::PreemptEnded(1)),
+        ]
+    );
+}
+
+#[test]
+fn full_log_drops_the_oldest() {
+    let mut log = EventLog::new();
+    for second in 0..LOG_LEN + 3 {
+        log.record(at(second), Event::PreemptCalled(0));
+    }
+
+    let times: Vec<_> = log.iter().map(|entry| entry.time).collect();
+    assert_eq!(times.len(), LOG_LEN);
+    assert_eq!(times[0], at(3));
+    assert_eq!(times[LOG_LEN - 1], at(LOG_LEN + 2));
+}
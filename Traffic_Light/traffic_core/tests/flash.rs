@@ -0,0 +1,233 @@
+mod common;
+
+use common::Lamps;
+use traffic_core::Controller;
+use traffic_core::controller::FlashCause;
+use traffic_core::intensity::NORMAL;
+use traffic_core::pedestrian::{Crossing, PedHead, PedTiming};
+use traffic_core::phase::{
+    Approach, Intersection, Movement, Phase, PhaseDuration, Ring, Signal, SignalHead,
+};
+use traffic_core::timing::{ApproachTiming, STANDARD_PLAN};
+
+const MAIN: SignalHead = SignalHead {
+    red: 0,
+    yellow: 1,
+    green: 2,
+};
+const SIDE: SignalHead = SignalHead {
+    red: 3,
+    yellow: 4,
+    green: 5,
+};
+const WALK: u16 = 6;
+const DONT_WALK: u16 = 7;
+
+const APPROACHES: [Approach; 2] = [
+    Approach {
+        through: MAIN,
+        turn: None,
+        intensity: 20,
+        timing: None,
+        flash: Signal::Yellow,
+    },
+    Approach {
+        through: SIDE,
+        turn: None,
+        intensity: 21,
+        timing: None,
+        flash: Signal::Red,
+    },
+];
+const PHASES: [Phase; 2] = [
+    Phase {
+        movements: &[Movement::Through(0)],
+        green: PhaseDuration::Fixed(10),
+        yellow: PhaseDuration::Fixed(3),
+    },
+    Phase {
+        movements: &[Movement::Through(1)],
+        green: PhaseDuration::Fixed(10),
+        yellow: PhaseDuration::Fixed(3),
+    },
+];
+const RINGS: [Ring; 1] = [Ring {
+    groups: &[&[0], &[1]],
+}];
+const CROSSINGS: [Crossing; 1] = [Crossing {
+    head: PedHead {
+        walk: WALK,
+        dont_walk: DONT_WALK,
+    },
+    button: 22,
+    phase: 0,
+    timing: PedTiming {
+        walk: 4,
+        clearance: 4,
+        buffer: 1,
+    },
+}];
+const INTERSECTION: Intersection = Intersection {
+    approaches: &APPROACHES,
+    phases: &PHASES,
+    rings: &RINGS,
+    crossings: &CROSSINGS,
+};
+
+// The same roads with both greens actuated.
+const TIMING: ApproachTiming = ApproachTiming {
+    min_green: 5,
+    passage: 2,
+    max_green: 10,
+};
+const ACTUATED_APPROACHES: [Approach; 2] = [
+    Approach {
+        timing: Some(TIMING),
+        ..APPROACHES[0]
+    },
+    Approach {
+        timing: Some(TIMING),
+        ..APPROACHES[1]
+    },
+];
+const ACTUATED_PHASES: [Phase; 2] = [
+    Phase {
+        green: PhaseDuration::Actuated,
+        ..PHASES[0]
+    },
+    Phase {
+        green: PhaseDuration::Actuated,
+        ..PHASES[1]
+    },
+];
+const ACTUATED: Intersection = Intersection {
+    approaches: &ACTUATED_APPROACHES,
+    phases: &ACTUATED_PHASES,
+    rings: &RINGS,
+    crossings: &[],
+};
+
+const LEVELS: [u8; 2] = [NORMAL, NORMAL];
+
+fn assert_flashing(lamps: &Lamps) {
+    assert!(lamps.flashing(MAIN.yellow) && lamps.flashing(SIDE.red));
+    for lamp in [MAIN.red, MAIN.green, SIDE.yellow, SIDE.green] {
+        assert!(!lamps.lit(lamp) && !lamps.flashing(lamp));
+    }
+    assert!(!lamps.lit(WALK) && !lamps.lit(DONT_WALK));
+}
+
+#[test]
+fn scheduled_flash_waits_for_the_end_of_the_cycle() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    let mut wait = controller.update(0, &LEVELS, &mut lamps);
+    controller.enter_flash(FlashCause::Schedule);
+
+    // Both phases still get their green.
+    let mut served = [false; 2];
+    while controller.flashing().is_none() {
+        for (approach, served) in served.iter_mut().enumerate() {
+            *served |= controller.signal(Movement::Through(approach)) == Signal::Green;
+        }
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+    assert_eq!(served, [true, true]);
+    assert_eq!(controller.flashing(), Some(FlashCause::Schedule));
+    assert_flashing(&lamps);
+}
+
+#[test]
+fn fault_flashes_at_once() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    controller.call_pedestrians(1);
+    controller.update(0, &LEVELS, &mut lamps);
+    assert!(lamps.lit(WALK));
+
+    controller.enter_flash(FlashCause::Fault);
+    controller.update(0, &LEVELS, &mut lamps);
+    assert_eq!(controller.flashing(), Some(FlashCause::Fault));
+    assert_flashing(&lamps);
+}
+
+#[test]
+fn fault_during_a_scheduled_flash_becomes_the_cause() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    controller.enter_flash(FlashCause::Schedule);
+    controller.update(0, &LEVELS, &mut lamps);
+    controller.enter_flash(FlashCause::Fault);
+    assert_eq!(controller.flashing(), Some(FlashCause::Fault));
+
+    // A later schedule request does not take it back.
+    controller.enter_flash(FlashCause::Schedule);
+    controller.update(0, &LEVELS, &mut lamps);
+    assert_eq!(controller.flashing(), Some(FlashCause::Fault));
+    assert_flashing(&lamps);
+}
+
+#[test]
+fn flash_requested_before_start_up_begins_flashing() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    controller.enter_flash(FlashCause::Schedule);
+    controller.update(0, &LEVELS, &mut lamps);
+    assert_flashing(&lamps);
+    assert!(!lamps.lit(MAIN.green));
+}
+
+#[test]
+fn leaving_flash_shows_steady_red_before_the_first_green() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    controller.enter_flash(FlashCause::Command);
+    controller.update(0, &LEVELS, &mut lamps);
+    controller.leave_flash();
+    assert_eq!(controller.flashing(), None);
+
+    assert_eq!(
+        controller.update(0, &LEVELS, &mut lamps),
+        STANDARD_PLAN.startup_red
+    );
+    for lamp in [MAIN.red, SIDE.red] {
+        assert!(lamps.lit(lamp) && !lamps.flashing(lamp));
+    }
+    assert!(!lamps.flashing(MAIN.yellow) && !lamps.lit(MAIN.yellow));
+
+    assert_eq!(
+        controller.update(STANDARD_PLAN.startup_red, &LEVELS, &mut lamps),
+        10
+    );
+    assert_eq!(controller.signal(Movement::Through(0)), Signal::Green);
+}
+
+#[test]
+fn leaving_flash_serves_every_actuated_phase_without_detections() {
+    let mut controller = Controller::new(&ACTUATED, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    // Run a cycle so the calls placed at start-up are used up.
+    let mut wait = controller.update(0, &LEVELS, &mut lamps);
+    while controller.signal(Movement::Through(1)) != Signal::Green {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+    assert_eq!(controller.demand(), 0);
+
+    controller.enter_flash(FlashCause::Fault);
+    controller.update(wait, &LEVELS, &mut lamps);
+    controller.leave_flash();
+
+    wait = controller.update(0, &LEVELS, &mut lamps);
+    assert_eq!(wait, STANDARD_PLAN.startup_red);
+    assert_eq!(
+        controller.update(wait, &LEVELS, &mut lamps),
+        TIMING.min_green
+    );
+    assert_eq!(controller.signal(Movement::Through(0)), Signal::Green);
+}
@@ -0,0 +1,77 @@
+use traffic_core::monitor::{ConflictMonitor, Fault, TRIP_CHECKS};
+use traffic_core::phase::SignalHead;
+
+const HEADS: [SignalHead; 3] = [
+    SignalHead {
+        red: 0,
+        yellow: 1,
+        green: 2,
+    },
+    SignalHead {
+        red: 3,
+        yellow: 4,
+        green: 5,
+    },
+    SignalHead {
+        red: 6,
+        yellow: 7,
+        green: 8,
+    },
+];
+
+// Heads 0 and 2 run together; head 1 conflicts with both.
+const COMPATIBLE: [u16; 3] = [0b101, 0b010, 0b101];
+
+fn lit(lamps: &[u16]) -> u32 {
+    lamps.iter().fold(0, |bits, lamp| bits | (1 << lamp))
+}
+
+fn trip(monitor: &mut ConflictMonitor, lamps: &[u16]) -> Option<Fault> {
+    (0..TRIP_CHECKS).fold(None, |_, _| monitor.check(lit(lamps)))
+}
+
+#[test]
+fn compatible_greens_pass() {
+    let mut monitor = ConflictMonitor::new(&HEADS, &COMPATIBLE);
+    assert_eq!(trip(&mut monitor, &[2, 3, 8]), None);
+    assert_eq!(trip(&mut monitor, &[0, 5, 6]), None);
+}
+
+#[test]
+fn conflicting_greens_trip() {
+    let mut monitor = ConflictMonitor::new(&HEADS, &COMPATIBLE);
+    assert_eq!(
+        trip(&mut monitor, &[2, 5, 6]),
+        Some(Fault::ConflictingGreens(0, 1))
+    );
+}
+
+#[test]
+fn conflicting_heads_need_a_red() {
+    let mut monitor = ConflictMonitor::new(&HEADS, &COMPATIBLE);
+    assert_eq!(
+        trip(&mut monitor, &[0, 4, 7]),
+        Some(Fault::MissingRed(1, 2))
+    );
+}
+
+#[test]
+fn dark_head_trips() {
+    let mut monitor = ConflictMonitor::new(&HEADS, &COMPATIBLE);
+    assert_eq!(trip(&mut monitor, &[0, 3]), Some(Fault::Dark(2)));
+}
+
+#[test]
+fn single_glitch_does_not_trip() {
+    let mut monitor = ConflictMonitor::new(&HEADS, &COMPATIBLE);
+    assert_eq!(monitor.check(lit(&[2, 5, 6])), None);
+    assert_eq!(monitor.check(lit(&[2, 3, 8])), None);
+    assert_eq!(monitor.fault(), None);
+}
+
+#[test]
+fn fault_stays_latched() {
+    let mut monitor = ConflictMonitor::new(&HEADS, &COMPATIBLE);
+    trip(&mut monitor, &[0, 3]);
+    assert_eq!(monitor.check(lit(&[2, 3, 8])), Some(Fault::Dark(2)));
+}
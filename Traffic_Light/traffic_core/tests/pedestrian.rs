@@ -0,0 +1,127 @@
+mod common;
+
+use common::Lamps;
+use traffic_core::Controller;
+use traffic_core::intensity::NORMAL;
+use traffic_core::pedestrian::{Crossing, PedHead, PedSignal, PedTiming};
+use traffic_core::phase::{
+    Approach, Intersection, Movement, Phase, PhaseDuration, Ring, Signal, SignalHead,
+};
+use traffic_core::timing::STANDARD_PLAN;
+
+const APPROACHES: [Approach; 2] = [
+    Approach {
+        through: SignalHead {
+            red: 0,
+            yellow: 1,
+            green: 2,
+        },
+        turn: None,
+        intensity: 20,
+        timing: None,
+        flash: Signal::Red,
+    },
+    Approach {
+        through: SignalHead {
+            red: 3,
+            yellow: 4,
+            green: 5,
+        },
+        turn: None,
+        intensity: 21,
+        timing: None,
+        flash: Signal::Red,
+    },
+];
+const PHASES: [Phase; 2] = [
+    Phase {
+        movements: &[Movement::Through(1)],
+        green: PhaseDuration::Fixed(10),
+        yellow: PhaseDuration::Fixed(3),
+    },
+    Phase {
+        movements: &[Movement::Through(0)],
+        green: PhaseDuration::Fixed(10),
+        yellow: PhaseDuration::Fixed(3),
+    },
+];
+const RINGS: [Ring; 1] = [Ring {
+    groups: &[&[0], &[1]],
+}];
+
+// People cross approach 0 while approach 1 has the green.
+const WALK: u16 = 6;
+const DONT_WALK: u16 = 7;
+const CROSSINGS: [Crossing; 1] = [Crossing {
+    head: PedHead {
+        walk: WALK,
+        dont_walk: DONT_WALK,
+    },
+    button: 22,
+    phase: 0,
+    timing: PedTiming {
+        walk: 7,
+        clearance: 9,
+        buffer: 2,
+    },
+}];
+const INTERSECTION: Intersection = Intersection {
+    approaches: &APPROACHES,
+    phases: &PHASES,
+    rings: &RINGS,
+    crossings: &CROSSINGS,
+};
+
+const LEVELS: [u8; 2] = [NORMAL, NORMAL];
+
+#[test]
+fn uncalled_crossing_stays_dont_walk() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    assert_eq!(controller.update(0, &LEVELS, &mut lamps), 10);
+    assert_eq!(controller.ped_signal(0), PedSignal::DontWalk);
+    assert!(lamps.lit(DONT_WALK) && !lamps.lit(WALK));
+}
+
+#[test]
+fn call_is_served_on_the_next_compatible_green() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    // Phase 0 is already green when the button is pressed.
+    controller.update(0, &LEVELS, &mut lamps);
+    controller.call_pedestrians(1);
+    let mut wait = controller.update(10, &LEVELS, &mut lamps);
+    assert_eq!(controller.ped_signal(0), PedSignal::DontWalk);
+
+    while controller.signal(Movement::Through(1)) != Signal::Green {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+    assert_eq!(controller.ped_calls(), 0);
+    assert_eq!(controller.ped_signal(0), PedSignal::Walk);
+    assert!(lamps.lit(WALK) && !lamps.lit(DONT_WALK));
+}
+
+#[test]
+fn walk_clearance_and_buffer_fit_inside_the_green() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    controller.call_pedestrians(1);
+    assert_eq!(controller.update(0, &LEVELS, &mut lamps), 7);
+    assert_eq!(controller.ped_signal(0), PedSignal::Walk);
+
+    assert_eq!(controller.update(7, &LEVELS, &mut lamps), 9);
+    assert_eq!(controller.ped_signal(0), PedSignal::FlashingDontWalk);
+    assert!(lamps.flashing(DONT_WALK) && !lamps.lit(WALK));
+
+    // The phase's own green is 10 s, stretched to 18 s for the crossing.
+    assert_eq!(controller.update(9, &LEVELS, &mut lamps), 2);
+    assert_eq!(controller.ped_signal(0), PedSignal::DontWalk);
+    assert!(lamps.lit(DONT_WALK) && !lamps.flashing(DONT_WALK));
+    assert_eq!(controller.signal(Movement::Through(1)), Signal::Green);
+
+    assert_eq!(controller.update(2, &LEVELS, &mut lamps), 3);
+    assert_eq!(controller.signal(Movement::Through(1)), Signal::Yellow);
+}
@@ -0,0 +1,236 @@
+mod common;
+
+use common::Lamps;
+use traffic_core::Controller;
+use traffic_core::controller::PreemptStage;
+use traffic_core::intensity::NORMAL;
+use traffic_core::pedestrian::{Crossing, PedHead, PedSignal, PedTiming};
+use traffic_core::phase::{
+    Approach, Intersection, Movement, Phase, PhaseDuration, Ring, Signal, SignalHead,
+};
+use traffic_core::timing::{ApproachTiming, STANDARD_PLAN};
+
+const APPROACHES: [Approach; 2] = [
+    Approach {
+        through: SignalHead {
+            red: 0,
+            yellow: 1,
+            green: 2,
+        },
+        turn: None,
+        intensity: 20,
+        timing: None,
+        flash: Signal::Yellow,
+    },
+    Approach {
+        through: SignalHead {
+            red: 3,
+            yellow: 4,
+            green: 5,
+        },
+        turn: None,
+        intensity: 21,
+        timing: None,
+        flash: Signal::Red,
+    },
+];
+const PHASES: [Phase; 2] = [
+    Phase {
+        movements: &[Movement::Through(0)],
+        green: PhaseDuration::Fixed(20),
+        yellow: PhaseDuration::Fixed(3),
+    },
+    Phase {
+        movements: &[Movement::Through(1)],
+        green: PhaseDuration::Fixed(20),
+        yellow: PhaseDuration::Fixed(4),
+    },
+];
+const RINGS: [Ring; 1] = [Ring {
+    groups: &[&[0], &[1]],
+}];
+// People cross approach 1 while approach 0 has the green.
+const CROSSINGS: [Crossing; 1] = [Crossing {
+    head: PedHead {
+        walk: 6,
+        dont_walk: 7,
+    },
+    button: 22,
+    phase: 0,
+    timing: PedTiming {
+        walk: 7,
+        clearance: 5,
+        buffer: 1,
+    },
+}];
+const INTERSECTION: Intersection = Intersection {
+    approaches: &APPROACHES,
+    phases: &PHASES,
+    rings: &RINGS,
+    crossings: &CROSSINGS,
+};
+
+// The same roads with both greens actuated and nobody crossing.
+const TIMING: ApproachTiming = ApproachTiming {
+    min_green: 5,
+    passage: 2,
+    max_green: 10,
+};
+const ACTUATED_APPROACHES: [Approach; 2] = [
+    Approach {
+        timing: Some(TIMING),
+        ..APPROACHES[0]
+    },
+    Approach {
+        timing: Some(TIMING),
+        ..APPROACHES[1]
+    },
+];
+const ACTUATED_PHASES: [Phase; 2] = [
+    Phase {
+        green: PhaseDuration::Actuated,
+        ..PHASES[0]
+    },
+    Phase {
+        green: PhaseDuration::Actuated,
+        ..PHASES[1]
+    },
+];
+const ACTUATED: Intersection = Intersection {
+    approaches: &ACTUATED_APPROACHES,
+    phases: &ACTUATED_PHASES,
+    rings: &RINGS,
+    crossings: &[],
+};
+
+const LEVELS: [u8; 2] = [NORMAL, NORMAL];
+
+fn signal(controller: &Controller, approach: usize) -> Signal {
+    controller.signal(Movement::Through(approach))
+}
+
+#[test]
+fn conflicting_green_clears_before_the_dwell() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    assert_eq!(controller.update(0, &LEVELS, &mut lamps), 20);
+    assert_eq!(controller.update(5, &LEVELS, &mut lamps), 15);
+
+    controller.preempt(1, 0);
+    assert_eq!(controller.update(0, &LEVELS, &mut lamps), 3);
+    assert_eq!(signal(&controller, 0), Signal::Yellow);
+    assert_eq!(controller.preemption(), Some((1, PreemptStage::Clearing)));
+
+    assert_eq!(controller.update(3, &LEVELS, &mut lamps), 2);
+    assert_eq!(signal(&controller, 0), Signal::Red);
+    assert_eq!(signal(&controller, 1), Signal::Red);
+
+    // Held green for as long as the call lasts.
+    controller.update(2, &LEVELS, &mut lamps);
+    assert_eq!(controller.preemption(), Some((1, PreemptStage::Dwell)));
+    controller.update(60, &LEVELS, &mut lamps);
+    assert_eq!(signal(&controller, 1), Signal::Green);
+    assert!(lamps.lit(5) && lamps.lit(0));
+}
+
+#[test]
+fn sequence_resumes_at_the_reentry_phase() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    controller.update(0, &LEVELS, &mut lamps);
+    controller.preempt(1, 0);
+    let mut wait = controller.update(0, &LEVELS, &mut lamps);
+    while controller.preemption() != Some((1, PreemptStage::Dwell)) {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+
+    controller.clear_preempt(1);
+    assert_eq!(controller.update(0, &LEVELS, &mut lamps), 4);
+    assert_eq!(signal(&controller, 1), Signal::Yellow);
+    assert_eq!(controller.update(4, &LEVELS, &mut lamps), 2);
+    assert_eq!(controller.update(2, &LEVELS, &mut lamps), 20);
+    assert_eq!(controller.preemption(), None);
+    assert_eq!(signal(&controller, 0), Signal::Green);
+}
+
+#[test]
+fn walk_is_cut_short_but_its_clearance_runs_in_full() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    controller.call_pedestrians(1);
+    assert_eq!(controller.update(0, &LEVELS, &mut lamps), 7);
+    assert_eq!(controller.ped_signal(0), PedSignal::Walk);
+
+    controller.preempt(1, 0);
+    assert_eq!(controller.update(0, &LEVELS, &mut lamps), 5);
+    assert_eq!(controller.ped_signal(0), PedSignal::FlashingDontWalk);
+    assert_eq!(signal(&controller, 0), Signal::Green);
+
+    assert_eq!(controller.update(5, &LEVELS, &mut lamps), 1);
+    assert_eq!(signal(&controller, 0), Signal::Yellow);
+}
+
+#[test]
+fn running_yellow_is_not_shortened() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    controller.update(0, &LEVELS, &mut lamps);
+    assert_eq!(controller.update(20, &LEVELS, &mut lamps), 3);
+    assert_eq!(controller.update(1, &LEVELS, &mut lamps), 2);
+
+    controller.preempt(1, 0);
+    assert_eq!(controller.update(0, &LEVELS, &mut lamps), 2);
+    assert_eq!(signal(&controller, 0), Signal::Yellow);
+}
+
+#[test]
+fn approach_already_green_is_simply_held() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    controller.update(0, &LEVELS, &mut lamps);
+    controller.preempt(0, 1);
+    controller.update(0, &LEVELS, &mut lamps);
+    assert_eq!(controller.preemption(), Some((0, PreemptStage::Dwell)));
+    controller.update(60, &LEVELS, &mut lamps);
+    assert_eq!(signal(&controller, 0), Signal::Green);
+
+    // Re-entering at phase 1 serves the other approach next.
+    controller.clear_preempt(0);
+    let mut wait = controller.update(0, &LEVELS, &mut lamps);
+    while controller.preemption().is_some() {
+        assert_ne!(signal(&controller, 1), Signal::Green);
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+    assert_eq!(signal(&controller, 1), Signal::Green);
+}
+
+#[test]
+fn reentry_phase_is_served_without_detections() {
+    let mut controller = Controller::new(&ACTUATED, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    // Serve the start-up calls, then preempt with nobody else waiting.
+    let mut wait = controller.update(0, &LEVELS, &mut lamps);
+    while signal(&controller, 1) != Signal::Green {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+    assert_eq!(controller.demand(), 0);
+    controller.preempt(1, 0);
+    wait = controller.update(0, &LEVELS, &mut lamps);
+    while controller.preemption() != Some((1, PreemptStage::Dwell)) {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+
+    controller.clear_preempt(1);
+    wait = controller.update(0, &LEVELS, &mut lamps);
+    while controller.preemption().is_some() {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+    assert_eq!(wait, TIMING.min_green);
+    assert_eq!(signal(&controller, 0), Signal::Green);
+}
@@ -0,0 +1,185 @@
+mod common;
+
+use common::Lamps;
+use traffic_core::Controller;
+use traffic_core::controller::{PreemptStage, RailPreempt, RailStage};
+use traffic_core::intensity::NORMAL;
+use traffic_core::phase::{
+    Approach, Intersection, Movement, Phase, PhaseDuration, Ring, Signal, SignalHead,
+};
+use traffic_core::timing::STANDARD_PLAN;
+
+const fn approach(first: u16) -> Approach {
+    Approach {
+        through: SignalHead {
+            red: first,
+            yellow: first + 1,
+            green: first + 2,
+        },
+        turn: None,
+        intensity: 20 + first,
+        timing: None,
+        flash: Signal::Red,
+    }
+}
+
+const fn phase(movements: &'static [Movement]) -> Phase<'static> {
+    Phase {
+        movements,
+        green: PhaseDuration::Fixed(20),
+        yellow: PhaseDuration::Fixed(3),
+    }
+}
+
+// Vehicles on TRACKS stop over the rails while waiting; SIDE and MAIN never
+// cross them.
+const TRACKS: usize = 0;
+const SIDE: usize = 1;
+const MAIN: usize = 2;
+const APPROACHES: [Approach; 3] = [approach(0), approach(3), approach(6)];
+const PHASES: [Phase; 3] = [
+    phase(&[Movement::Through(TRACKS)]),
+    phase(&[Movement::Through(SIDE)]),
+    phase(&[Movement::Through(MAIN)]),
+];
+const RINGS: [Ring; 1] = [Ring {
+    groups: &[&[0], &[1], &[2]],
+}];
+const INTERSECTION: Intersection = Intersection {
+    approaches: &APPROACHES,
+    phases: &PHASES,
+    rings: &RINGS,
+    crossings: &[],
+};
+
+const RAIL: RailPreempt = RailPreempt {
+    track_clearance: 0,
+    clearance_green: 10,
+    hold: &[2],
+    reentry: 1,
+};
+const RAIL_CYCLING: RailPreempt = RailPreempt {
+    hold: &[1, 2],
+    ..RAIL
+};
+
+const LEVELS: [u8; 3] = [NORMAL; 3];
+
+fn signal(controller: &Controller, approach: usize) -> Signal {
+    controller.signal(Movement::Through(approach))
+}
+
+#[test]
+fn track_clearance_runs_then_the_hold_phase_rests() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    controller.update(0, &LEVELS, &mut lamps);
+    controller.update(20, &LEVELS, &mut lamps);
+    controller.update(3, &LEVELS, &mut lamps);
+    controller.update(2, &LEVELS, &mut lamps);
+    assert_eq!(signal(&controller, SIDE), Signal::Green);
+
+    controller.rail_preempt(&RAIL);
+    assert_eq!(controller.update(0, &LEVELS, &mut lamps), 3);
+    assert_eq!(signal(&controller, SIDE), Signal::Yellow);
+    assert_eq!(controller.update(3, &LEVELS, &mut lamps), 2);
+
+    assert_eq!(controller.update(2, &LEVELS, &mut lamps), 10);
+    assert_eq!(
+        controller.rail_preemption(),
+        Some(RailStage::TrackClearance)
+    );
+    assert_eq!(signal(&controller, TRACKS), Signal::Green);
+
+    controller.update(10, &LEVELS, &mut lamps);
+    controller.update(3, &LEVELS, &mut lamps);
+    controller.update(2, &LEVELS, &mut lamps);
+    assert_eq!(controller.rail_preemption(), Some(RailStage::Hold));
+    assert_eq!(signal(&controller, MAIN), Signal::Green);
+
+    for _ in 0..10 {
+        controller.update(60, &LEVELS, &mut lamps);
+        assert_eq!(signal(&controller, MAIN), Signal::Green);
+        assert!(lamps.lit(0) && !lamps.lit(2) && lamps.lit(3));
+    }
+}
+
+#[test]
+fn clearance_green_already_showing_still_runs_in_full() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    controller.update(0, &LEVELS, &mut lamps);
+    controller.update(18, &LEVELS, &mut lamps);
+    controller.rail_preempt(&RAIL);
+    assert_eq!(controller.update(0, &LEVELS, &mut lamps), 10);
+    assert_eq!(signal(&controller, TRACKS), Signal::Green);
+}
+
+#[test]
+fn hold_phases_take_turns_without_the_tracks() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    controller.update(0, &LEVELS, &mut lamps);
+    controller.rail_preempt(&RAIL_CYCLING);
+    let mut wait = controller.update(0, &LEVELS, &mut lamps);
+    while controller.rail_preemption() != Some(RailStage::Hold) {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+
+    let mut served = [false; 3];
+    for _ in 0..30 {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+        for (approach, served) in served.iter_mut().enumerate() {
+            *served |= signal(&controller, approach) == Signal::Green;
+        }
+    }
+    assert_eq!(served, [false, true, true]);
+}
+
+#[test]
+fn lifted_gates_resume_at_the_reentry_phase() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    controller.update(0, &LEVELS, &mut lamps);
+    controller.rail_preempt(&RAIL);
+    let mut wait = controller.update(0, &LEVELS, &mut lamps);
+    while controller.rail_preemption() != Some(RailStage::Hold) {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+
+    // The hold green has only just started and runs its time out.
+    controller.clear_rail_preempt();
+    assert_eq!(controller.update(0, &LEVELS, &mut lamps), 20);
+    assert_eq!(controller.rail_preemption(), Some(RailStage::Exit));
+    assert_eq!(controller.update(20, &LEVELS, &mut lamps), 3);
+    assert_eq!(signal(&controller, MAIN), Signal::Yellow);
+
+    controller.update(3, &LEVELS, &mut lamps);
+    assert_eq!(controller.update(2, &LEVELS, &mut lamps), 20);
+    assert_eq!(controller.rail_preemption(), None);
+    assert_eq!(signal(&controller, SIDE), Signal::Green);
+}
+
+#[test]
+fn railroad_overrides_emergency_preemption() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    controller.update(0, &LEVELS, &mut lamps);
+    controller.preempt(SIDE, 2);
+    let mut wait = controller.update(0, &LEVELS, &mut lamps);
+    while controller.preemption() != Some((SIDE, PreemptStage::Dwell)) {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+
+    controller.rail_preempt(&RAIL);
+    controller.preempt(SIDE, 2);
+    controller.update(0, &LEVELS, &mut lamps);
+    assert_eq!(controller.preemption(), None);
+    assert_eq!(controller.rail_preemption(), Some(RailStage::Clearing));
+    assert_eq!(signal(&controller, SIDE), Signal::Yellow);
+}
@@ -0,0 +1,220 @@
+mod common;
+
+use common::Lamps;
+use traffic_core::Controller;
+use traffic_core::intensity::NORMAL;
+use traffic_core::phase::{
+    Approach, Intersection, Movement, Phase, PhaseDuration, Ring, Signal, SignalHead,
+};
+use traffic_core::schedule::{Days, Schedule, ScheduleEntry, TimeOfDay, WeekTime, Weekday};
+use traffic_core::timing::{STANDARD_PLAN, TimingPlan};
+
+const PEAK: TimingPlan = TimingPlan {
+    normal: [40, 5, 20, 5],
+    ..STANDARD_PLAN
+};
+const NIGHT: TimingPlan = TimingPlan {
+    normal: [8, 3, 8, 3],
+    all_red: 3,
+    ..STANDARD_PLAN
+};
+const SCHEDULE: Schedule = Schedule {
+    entries: &[
+        ScheduleEntry {
+            days: Days::EVERY_DAY,
+            start: TimeOfDay::new(6, 30, 0),
+            plan: &PEAK,
+            flash: false,
+        },
+        ScheduleEntry {
+            days: Days::EVERY_DAY,
+            start: TimeOfDay::new(9, 30, 0),
+            plan: &STANDARD_PLAN,
+            flash: false,
+        },
+        ScheduleEntry {
+            days: Days::EVERY_DAY,
+            start: TimeOfDay::new(22, 0, 0),
+            plan: &NIGHT,
+            flash: false,
+        },
+    ],
+};
+
+const APPROACHES: [Approach; 2] = [
+    Approach {
+        through: SignalHead {
+            red: 0,
+            yellow: 1,
+            green: 2,
+        },
+        turn: None,
+        intensity: 20,
+        timing: None,
+        flash: Signal::Red,
+    },
+    Approach {
+        through: SignalHead {
+            red: 3,
+            yellow: 4,
+            green: 5,
+        },
+        turn: None,
+        intensity: 21,
+        timing: None,
+        flash: Signal::Red,
+    },
+];
+const PHASES: [Phase; 2] = [
+    Phase {
+        movements: &[Movement::Through(1)],
+        green: PhaseDuration::Delay(0),
+        yellow: PhaseDuration::Delay(1),
+    },
+    Phase {
+        movements: &[Movement::Through(0)],
+        green: PhaseDuration::Delay(2),
+        yellow: PhaseDuration::Delay(3),
+    },
+];
+const RINGS: [Ring; 1] = [Ring {
+    groups: &[&[0], &[1]],
+}];
+const INTERSECTION: Intersection = Intersection {
+    approaches: &APPROACHES,
+    phases: &PHASES,
+    rings: &RINGS,
+    crossings: &[],
+};
+
+const LEVELS: [u8; 2] = [NORMAL, NORMAL];
+
+// Weekday peaks; the weekend keeps the off-peak plan all day.
+const WEEK: Schedule = Schedule {
+    entries: &[
+        ScheduleEntry {
+            days: Days::WEEKDAYS,
+            start: TimeOfDay::new(6, 30, 0),
+            plan: &PEAK,
+            flash: false,
+        },
+        ScheduleEntry {
+            days: Days::EVERY_DAY,
+            start: TimeOfDay::new(9, 30, 0),
+            plan: &STANDARD_PLAN,
+            flash: false,
+        },
+        ScheduleEntry {
+            days: Days::only(Weekday::Friday),
+            start: TimeOfDay::new(22, 0, 0),
+            plan: &NIGHT,
+            flash: true,
+        },
+    ],
+};
+
+fn at(day: Weekday, hour: u8, minute: u8) -> WeekTime {
+    WeekTime::new(day, TimeOfDay::new(hour, minute, 0))
+}
+
+fn plan_at(hour: u8, minute: u8) -> [u16; 4] {
+    SCHEDULE
+        .plan_at(at(Weekday::Monday, hour, minute))
+        .unwrap()
+        .normal
+}
+
+#[test]
+fn each_plan_runs_from_its_start_until_the_next() {
+    assert_eq!(plan_at(6, 30), PEAK.normal);
+    assert_eq!(plan_at(9, 29), PEAK.normal);
+    assert_eq!(plan_at(9, 30), STANDARD_PLAN.normal);
+    assert_eq!(plan_at(21, 59), STANDARD_PLAN.normal);
+    assert_eq!(plan_at(22, 0), NIGHT.normal);
+}
+
+#[test]
+fn last_plan_runs_past_midnight() {
+    assert_eq!(plan_at(0, 0), NIGHT.normal);
+    assert_eq!(plan_at(6, 29), NIGHT.normal);
+    assert!(
+        Schedule { entries: &[] }
+            .plan_at(at(Weekday::Monday, 12, 0))
+            .is_none()
+    );
+}
+
+#[test]
+fn weekdays_and_the_weekend_run_different_plans() {
+    let plan = |day, hour, minute| WEEK.plan_at(at(day, hour, minute)).unwrap().normal;
+    assert_eq!(plan(Weekday::Tuesday, 7, 0), PEAK.normal);
+    assert_eq!(plan(Weekday::Friday, 7, 0), PEAK.normal);
+    assert_eq!(plan(Weekday::Sunday, 7, 0), STANDARD_PLAN.normal);
+    assert_eq!(plan(Weekday::Saturday, 10, 0), STANDARD_PLAN.normal);
+}
+
+#[test]
+fn plan_carries_over_into_the_next_day() {
+    // Friday's night flash runs until the weekend's first entry.
+    let entry = WEEK.entry_at(at(Weekday::Saturday, 3, 0)).unwrap();
+    assert!(entry.flash);
+    assert_eq!(entry.plan.normal, NIGHT.normal);
+    assert!(
+        WEEK.entry_at(at(Weekday::Saturday, 9, 30))
+            .is_some_and(|entry| !entry.flash)
+    );
+
+    // Before Monday's peak it is still Sunday's off-peak plan.
+    let entry = WEEK.entry_at(at(Weekday::Monday, 6, 0)).unwrap();
+    assert!(!entry.flash);
+    assert_eq!(entry.plan.normal, STANDARD_PLAN.normal);
+}
+
+#[test]
+fn entry_for_one_day_runs_all_week_until_it_comes_round_again() {
+    let schedule = Schedule {
+        entries: &[ScheduleEntry {
+            days: Days::only(Weekday::Wednesday),
+            start: TimeOfDay::new(12, 0, 0),
+            plan: &NIGHT,
+            flash: false,
+        }],
+    };
+    assert!(schedule.plan_at(at(Weekday::Wednesday, 11, 0)).is_some());
+    assert!(schedule.plan_at(at(Weekday::Thursday, 0, 0)).is_some());
+}
+
+#[test]
+fn weekdays_follow_the_rtc_numbering() {
+    assert_eq!(Weekday::from_number(1), Some(Weekday::Monday));
+    assert_eq!(Weekday::from_number(7), Some(Weekday::Sunday));
+    assert_eq!(Weekday::from_number(0), None);
+    assert_eq!(Weekday::Monday.before(1), Weekday::Sunday);
+    assert_eq!(Weekday::Monday.before(7), Weekday::Monday);
+    assert!(Days::WEEKEND.contains(Weekday::Sunday));
+    assert!(!Days::WEEKDAYS.contains(Weekday::Saturday));
+}
+
+#[test]
+fn new_plan_waits_for_the_end_of_the_cycle() {
+    let mut controller = Controller::new(&INTERSECTION, STANDARD_PLAN);
+    let mut lamps = Lamps::default();
+
+    let mut wait = controller.update(0, &LEVELS, &mut lamps);
+    assert_eq!(wait, 15);
+    controller.set_plan(PEAK);
+
+    // The rest of this cycle keeps the old timing.
+    wait = controller.update(wait, &LEVELS, &mut lamps);
+    assert_eq!(controller.plan().normal, STANDARD_PLAN.normal);
+    while controller.signal(Movement::Through(0)) != Signal::Green {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+    assert_eq!(wait, 15);
+
+    while controller.signal(Movement::Through(1)) != Signal::Green {
+        wait = controller.update(wait, &LEVELS, &mut lamps);
+    }
+    assert_eq!(controller.plan().normal, PEAK.normal);
+    assert_eq!(wait, 40);
+}
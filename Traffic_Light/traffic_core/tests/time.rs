@@ -0,0 +1,45 @@
+use traffic_core::time::{Duration, Instant};
+
+#[test]
+fn units_convert_through_microseconds() {
+    assert_eq!(Duration::from_secs(2), Duration::from_millis(2000));
+    assert_eq!(Duration::from_millis(3).as_micros(), 3000);
+    assert_eq!(Duration::from_micros(2_500_000).as_secs(), 2);
+    assert_eq!(Duration::from_micros(2_500_000).as_millis(), 2500);
+}
+
+#[test]
+fn instants_move_by_durations() {
+    let start = Instant::from_micros(500);
+    let later = start + Duration::from_millis(10);
+    assert_eq!(later.as_micros(), 10_500);
+    assert_eq!(later - start, Duration::from_millis(10));
+    assert!(later > start);
+}
+
+#[test]
+fn going_backwards_saturates_at_zero() {
+    let start = Instant::from_micros(500);
+    assert_eq!(start - (start + Duration::from_secs(1)), Duration::ZERO);
+    assert_eq!(
+        Duration::from_millis(1) - Duration::from_secs(1),
+        Duration::ZERO
+    );
+}
+
+#[test]
+fn whole_counts_complete_units_only() {
+    let second = Duration::from_millis(200);
+    assert_eq!(Duration::from_millis(999).whole(second), 4);
+    assert_eq!(
+        second * 4 + Duration::from_millis(199),
+        Duration::from_millis(999)
+    );
+}
+
+#[test]
+fn time_base_runs_for_longer_than_32_bits_of_microseconds() {
+    let start = Instant::from_micros(u32::MAX as u64);
+    let later = start + Duration::from_secs(3600);
+    assert_eq!((later - start).as_secs(), 3600);
+}
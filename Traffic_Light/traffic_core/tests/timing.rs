@@ -0,0 +1,31 @@
+use traffic_core::intensity::{HIGH_INTENSE, INTENSE, NORMAL};
+use traffic_core::timing::get_traffic_delays;
+
+// Delays are [right green, right yellow, left green, left yellow].
+
+#[test]
+fn equal_intensities_run_the_normal_plan() {
+    for level in [NORMAL, INTENSE, HIGH_INTENSE] {
+        assert_eq!(get_traffic_delays(level, level), [15, 5, 15, 5]);
+    }
+}
+
+#[test]
+fn busier_left_gets_the_longer_green() {
+    assert_eq!(get_traffic_delays(INTENSE, NORMAL), [10, 5, 30, 5]);
+    assert_eq!(get_traffic_delays(HIGH_INTENSE, NORMAL), [10, 5, 50, 5]);
+    assert_eq!(get_traffic_delays(HIGH_INTENSE, INTENSE), [10, 5, 30, 5]);
+}
+
+#[test]
+fn busier_right_gets_the_longer_green() {
+    assert_eq!(get_traffic_delays(NORMAL, INTENSE), [30, 5, 10, 5]);
+    assert_eq!(get_traffic_delays(NORMAL, HIGH_INTENSE), [50, 5, 10, 5]);
+    assert_eq!(get_traffic_delays(INTENSE, HIGH_INTENSE), [30, 5, 10, 5]);
+}
+
+#[test]
+fn unknown_levels_fall_back_to_normal() {
+    assert_eq!(get_traffic_delays(7, NORMAL), [15, 5, 15, 5]);
+    assert_eq!(get_traffic_delays(INTENSE, 7), [15, 5, 15, 5]);
+}
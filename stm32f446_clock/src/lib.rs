@@ -0,0 +1,736 @@
+#![no_std]
+
+//! Clock tree planning for the STM32F446, shared by the projects in this
+//! repository.
+//!
+//! [`RccConfig`] works out PLL settings, prescalers, flash wait states and
+//! the regulator scale for the clocks asked for, at compile time where it
+//! can. [`Clocks`] carries the result to anything that divides a clock,
+//! and [`Measurement`] and [`hsi_trim`] check the clocks against a
+//! reference once they run. Nothing here touches the hardware.
+
+/// Frequencies the clock tree runs at, in hertz.
+///
+/// The clock set-up returns one once the system clock is switched over, and
+/// it does not change afterwards. Timers and anything else that counts
+/// clock cycles take their dividers from it instead of assuming a frequency.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Clocks {
+    sysclk: u32,
+    hclk: u32,
+    pclk1: u32,
+    pclk2: u32,
+    pll48clk: Option<u32>,
+    i2sclk: Option<u32>,
+}
+
+impl Clocks {
+    /// Clocks for `sysclk` divided down by the AHB prescaler `hpre` and the
+    /// APB1 and APB2 prescalers `ppre1` and `ppre2`.
+    pub const fn new(sysclk: u32, hpre: u32, ppre1: u32, ppre2: u32) -> Self {
+        let hclk = sysclk / hpre;
+        Clocks {
+            sysclk,
+            hclk,
+            pclk1: hclk / ppre1,
+            pclk2: hclk / ppre2,
+            pll48clk: None,
+            i2sclk: None,
+        }
+    }
+
+    pub const fn sysclk(&self) -> u32 {
+        self.sysclk
+    }
+
+    /// AHB clock, which also drives the core and its cycle counter.
+    pub const fn hclk(&self) -> u32 {
+        self.hclk
+    }
+
+    pub const fn pclk1(&self) -> u32 {
+        self.pclk1
+    }
+
+    pub const fn pclk2(&self) -> u32 {
+        self.pclk2
+    }
+
+    /// The 48 MHz clock of USB OTG FS and SDIO, if it was set up.
+    pub const fn pll48clk(&self) -> Option<u32> {
+        self.pll48clk
+    }
+
+    /// The PLLI2S R output clocking the I2S peripherals, if it was set up.
+    pub const fn i2sclk(&self) -> Option<u32> {
+        self.i2sclk
+    }
+
+    /// Clock of the timers on APB1. It runs at twice PCLK1 whenever APB1 is
+    /// divided down from HCLK.
+    pub const fn timclk1(&self) -> u32 {
+        timer_clock(self.hclk, self.pclk1)
+    }
+
+    /// Clock of the timers on APB2.
+    pub const fn timclk2(&self) -> u32 {
+        timer_clock(self.hclk, self.pclk2)
+    }
+}
+
+/// Peripherals to tell when the clocks change at runtime, so each can
+/// reprogram its own dividers.
+pub struct ClockUsers<const N: usize> {
+    users: [Option<fn(&Clocks)>; N],
+}
+
+impl<const N: usize> ClockUsers<N> {
+    pub const fn new() -> Self {
+        ClockUsers { users: [None; N] }
+    }
+
+    /// Adds `user`, or returns false if all `N` places are taken.
+    pub fn register(&mut self, user: fn(&Clocks)) -> bool {
+        match self.users.iter_mut().find(|place| place.is_none()) {
+            Some(place) => {
+                *place = Some(user);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Calls every user, in the order they registered, with the new clocks.
+    pub fn notify(&self, clocks: &Clocks) {
+        for user in self.users.iter().flatten() {
+            user(clocks);
+        }
+    }
+}
+
+impl<const N: usize> Default for ClockUsers<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const fn timer_clock(hclk: u32, pclk: u32) -> u32 {
+    if pclk == hclk { pclk } else { pclk * 2 }
+}
+
+/// Prescaler register value that makes a timer clocked at `timer_clock`
+/// count `rate` times a second.
+pub const fn timer_prescaler(timer_clock: u32, rate: u32) -> u32 {
+    timer_clock / rate - 1
+}
+
+/// A clock's frequency found by counting it against another, next to the
+/// frequency it is meant to run at.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Measurement {
+    pub nominal: u32,
+    pub measured: u32,
+}
+
+impl Measurement {
+    /// A clock that counted `ticks` over `cycles` of a `reference_hz` input,
+    /// as a timer does capturing the LSE. `None` if no cycles went by or
+    /// nothing was counted.
+    pub const fn of_counter(
+        nominal: u32,
+        reference_hz: u32,
+        cycles: u32,
+        ticks: u32,
+    ) -> Option<Self> {
+        if nominal == 0 || cycles == 0 || ticks == 0 {
+            return None;
+        }
+        Some(Measurement {
+            nominal,
+            measured: (reference_hz as u64 * ticks as u64 / cycles as u64) as u32,
+        })
+    }
+
+    /// An input that took `ticks` of a `counter_hz` clock for `cycles`, as
+    /// the LSI does captured by a timer. `None` if no cycles went by or
+    /// nothing was counted.
+    pub const fn of_input(nominal: u32, counter_hz: u32, cycles: u32, ticks: u32) -> Option<Self> {
+        if nominal == 0 || cycles == 0 || ticks == 0 {
+            return None;
+        }
+        Some(Measurement {
+            nominal,
+            measured: (counter_hz as u64 * cycles as u64 / ticks as u64) as u32,
+        })
+    }
+
+    /// How far off nominal the clock runs, in parts per million.
+    pub const fn error_ppm(&self) -> i32 {
+        let error = self.measured as i64 - self.nominal as i64;
+        (error * 1_000_000 / self.nominal as i64) as i32
+    }
+
+    pub const fn within_ppm(&self, ppm: u32) -> bool {
+        self.error_ppm().unsigned_abs() <= ppm
+    }
+}
+
+/// HSITRIM value bringing the HSI, measured as `hsi` while trimmed to
+/// `trim`, closest to 16 MHz.
+pub const fn hsi_trim(trim: u8, hsi: &Measurement) -> u8 {
+    let error = hsi.nominal as i64 - hsi.measured as i64;
+    let half_step = HSI_TRIM_STEP_HZ as i64 / 2;
+    let steps = if error < 0 {
+        (error - half_step) / HSI_TRIM_STEP_HZ as i64
+    } else {
+        (error + half_step) / HSI_TRIM_STEP_HZ as i64
+    };
+    let trimmed = trim as i64 + steps;
+    if trimmed < 0 {
+        0
+    } else if trimmed > HSI_TRIM_MAX as i64 {
+        HSI_TRIM_MAX
+    } else {
+        trimmed as u8
+    }
+}
+
+/// Internal RC oscillator, which the chip starts on.
+pub const HSI_HZ: u32 = 16_000_000;
+/// Low-speed external crystal, the most accurate clock on the board.
+pub const LSE_HZ: u32 = 32_768;
+/// Low-speed internal RC oscillator, good to no better than 50%.
+pub const LSI_HZ: u32 = 32_000;
+/// Rough HSI change per HSITRIM step.
+pub const HSI_TRIM_STEP_HZ: u32 = 80_000;
+/// HSITRIM value the chip starts with.
+pub const HSI_TRIM_DEFAULT: u8 = 16;
+const HSI_TRIM_MAX: u8 = 31;
+
+// STM32F446 datasheet limits.
+const HSE_RANGE: (u32, u32) = (4_000_000, 26_000_000);
+const VCO_IN_RANGE: (u32, u32) = (1_000_000, 2_000_000);
+const VCO_OUT_RANGE: (u32, u32) = (100_000_000, 432_000_000);
+const PLLM_RANGE: (u32, u32) = (2, 63);
+const PLLN_RANGE: (u32, u32) = (50, 432);
+const PLLQ_RANGE: (u32, u32) = (2, 15);
+const PLLR_RANGE: (u32, u32) = (2, 7);
+const PLLR_VALUES: [u32; 6] = [2, 3, 4, 5, 6, 7];
+const PLLP_VALUES: [u32; 4] = [2, 4, 6, 8];
+const HPRE_VALUES: [u32; 9] = [1, 2, 4, 8, 16, 64, 128, 256, 512];
+const PPRE_VALUES: [u32; 5] = [1, 2, 4, 8, 16];
+const SYSCLK_MAX: u32 = 180_000_000;
+const PCLK1_MAX: u32 = 45_000_000;
+const PCLK2_MAX: u32 = 90_000_000;
+/// HCLK each flash wait state covers with a 2.7-3.6 V supply.
+const FLASH_WS_HZ: u32 = 30_000_000;
+const PLL48_HZ: u32 = 48_000_000;
+/// Fastest HCLK without the regulator's over-drive.
+const OVERDRIVE_ABOVE_HZ: u32 = 168_000_000;
+
+/// Oscillator the PLL, or the system clock directly, runs from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ClockSource {
+    Hsi,
+    /// External crystal or clock at the given frequency.
+    Hse(u32),
+}
+
+impl ClockSource {
+    pub const fn hz(self) -> u32 {
+        match self {
+            ClockSource::Hsi => HSI_HZ,
+            ClockSource::Hse(hz) => hz,
+        }
+    }
+}
+
+/// Regulator output voltage scale; higher scales allow faster clocks.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum VoltageScale {
+    /// Up to 120 MHz.
+    Scale3,
+    /// Up to 144 MHz.
+    Scale2,
+    /// Up to 168 MHz, or 180 MHz with over-drive.
+    Scale1,
+}
+
+impl VoltageScale {
+    /// Lowest scale that allows `hclk`.
+    pub const fn for_hclk(hclk: u32) -> Self {
+        if hclk <= 120_000_000 {
+            VoltageScale::Scale3
+        } else if hclk <= 144_000_000 {
+            VoltageScale::Scale2
+        } else {
+            VoltageScale::Scale1
+        }
+    }
+
+    /// Value of the PWR_CR VOS field.
+    pub const fn bits(self) -> u8 {
+        match self {
+            VoltageScale::Scale3 => 0b01,
+            VoltageScale::Scale2 => 0b10,
+            VoltageScale::Scale1 => 0b11,
+        }
+    }
+}
+
+/// PLL dividers. The VCO runs at `source / m * n` and each output is the
+/// VCO divided by `p`, `q` or `r`.
+///
+/// On the main PLL P gives SYSCLK, Q the 48 MHz clock and R the I2S and
+/// SAI clock. On the PLLSAI P gives the 48 MHz clock and Q the SAI clock;
+/// it has no R. On the PLLI2S P gives SPDIF-RX, Q the SAI clock and R the
+/// I2S clock.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PllConfig {
+    pub m: u32,
+    pub n: u32,
+    pub p: u32,
+    pub q: u32,
+    pub r: u32,
+}
+
+impl PllConfig {
+    /// Value of the RCC_PLLCFGR PLLP field, coded the same for the PLLSAI
+    /// and PLLI2S.
+    pub const fn p_bits(&self) -> u8 {
+        (self.p / 2 - 1) as u8
+    }
+}
+
+/// Reasons a requested clock configuration cannot be met.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ClockError {
+    /// The HSE frequency is outside 4-26 MHz.
+    HseOutOfRange,
+    /// SYSCLK above 180 MHz.
+    SysclkTooFast,
+    /// No PLL dividers produce exactly the requested clocks.
+    NoPllSolution,
+    /// HCLK is not SYSCLK divided by an AHB prescaler.
+    HclkUnreachable,
+    /// PCLK1 or PCLK2 is not HCLK divided by an APB prescaler.
+    PclkUnreachable,
+    /// PCLK1 above 45 MHz or PCLK2 above 90 MHz.
+    PclkTooFast,
+    /// No PLLI2S dividers produce exactly the requested I2S clock.
+    NoPllI2sSolution,
+    /// The HSE did not report ready in time; the crystal or the clock
+    /// feeding it is missing.
+    HseNotReady,
+    /// The PLL did not lock in time.
+    PllNotReady,
+    /// The regulator did not finish entering over-drive in time.
+    OverdriveNotReady,
+}
+
+/// Where the 48 MHz clock comes from: the DCKCFGR2 CK48MSEL choice.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Clock48Source {
+    /// The main PLL's Q output.
+    PllQ,
+    /// The PLLSAI's P output, for when no main PLL dividers give SYSCLK
+    /// and 48 MHz together, as at 180 MHz.
+    PllSaiP,
+}
+
+/// Everything the clock set-up has to program, worked out by
+/// [`RccConfig::freeze`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ClockPlan {
+    pub source: ClockSource,
+    /// `None` when SYSCLK runs straight from the source.
+    pub pll: Option<PllConfig>,
+    /// `None` when nothing needs the PLLSAI.
+    pub pllsai: Option<PllConfig>,
+    /// `None` when no I2S clock was requested.
+    pub plli2s: Option<PllConfig>,
+    /// `None` when no 48 MHz clock was requested.
+    pub clock48: Option<Clock48Source>,
+    /// AHB, APB1 and APB2 divisors.
+    pub hpre: u32,
+    pub ppre1: u32,
+    pub ppre2: u32,
+    pub flash_latency: u8,
+    pub vos: VoltageScale,
+    /// Whether HCLK needs the regulator in over-drive.
+    pub overdrive: bool,
+    pub clocks: Clocks,
+}
+
+impl ClockPlan {
+    /// The same clocks generated from the HSI, for when the HSE fails.
+    pub const fn on_hsi(&self) -> Result<ClockPlan, ClockError> {
+        let mut config = RccConfig::new()
+            .sysclk(self.clocks.sysclk())
+            .hclk(self.clocks.hclk())
+            .pclk1(self.clocks.pclk1())
+            .pclk2(self.clocks.pclk2());
+        if self.clock48.is_some() {
+            config = config.require_pll48clk();
+        }
+        if let Some(hz) = self.clocks.i2sclk() {
+            config = config.i2sclk(hz);
+        }
+        config.freeze()
+    }
+
+    /// Value of the RCC_CFGR HPRE field.
+    pub const fn hpre_bits(&self) -> u8 {
+        match self.hpre {
+            1 => 0,
+            2 => 0b1000,
+            4 => 0b1001,
+            8 => 0b1010,
+            16 => 0b1011,
+            64 => 0b1100,
+            128 => 0b1101,
+            256 => 0b1110,
+            _ => 0b1111,
+        }
+    }
+
+    /// Value of the RCC_CFGR PPRE1 field.
+    pub const fn ppre1_bits(&self) -> u8 {
+        ppre_bits(self.ppre1)
+    }
+
+    /// Value of the RCC_CFGR PPRE2 field.
+    pub const fn ppre2_bits(&self) -> u8 {
+        ppre_bits(self.ppre2)
+    }
+}
+
+const fn ppre_bits(divisor: u32) -> u8 {
+    match divisor {
+        1 => 0,
+        2 => 0b100,
+        4 => 0b101,
+        8 => 0b110,
+        _ => 0b111,
+    }
+}
+
+/// Requested clock tree. Anything left unset takes the fastest value the
+/// limits allow: SYSCLK defaults to the source, HCLK to SYSCLK and the APB
+/// clocks to the fastest HCLK division within their maximum.
+///
+/// ```
+/// use stm32f446_clock::RccConfig;
+///
+/// let plan = RccConfig::new()
+///     .use_hse(8_000_000)
+///     .sysclk(180_000_000)
+///     .freeze()
+///     .unwrap();
+/// assert_eq!(plan.clocks.pclk1(), 45_000_000);
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RccConfig {
+    source: ClockSource,
+    sysclk: Option<u32>,
+    hclk: Option<u32>,
+    pclk1: Option<u32>,
+    pclk2: Option<u32>,
+    pll48: bool,
+    i2sclk: Option<u32>,
+}
+
+impl RccConfig {
+    /// Runs from the HSI with every clock at its default.
+    pub const fn new() -> Self {
+        RccConfig {
+            source: ClockSource::Hsi,
+            sysclk: None,
+            hclk: None,
+            pclk1: None,
+            pclk2: None,
+            pll48: false,
+            i2sclk: None,
+        }
+    }
+
+    pub const fn use_hse(mut self, hz: u32) -> Self {
+        self.source = ClockSource::Hse(hz);
+        self
+    }
+
+    pub const fn sysclk(mut self, hz: u32) -> Self {
+        self.sysclk = Some(hz);
+        self
+    }
+
+    pub const fn hclk(mut self, hz: u32) -> Self {
+        self.hclk = Some(hz);
+        self
+    }
+
+    pub const fn pclk1(mut self, hz: u32) -> Self {
+        self.pclk1 = Some(hz);
+        self
+    }
+
+    pub const fn pclk2(mut self, hz: u32) -> Self {
+        self.pclk2 = Some(hz);
+        self
+    }
+
+    /// Also requires exactly 48 MHz for USB OTG FS and SDIO. It comes from
+    /// the main PLL's Q output where the dividers allow, and from the PLLSAI
+    /// otherwise.
+    pub const fn require_pll48clk(mut self) -> Self {
+        self.pll48 = true;
+        self
+    }
+
+    /// Also requires exactly `hz` from the PLLI2S R output for the I2S
+    /// peripherals.
+    pub const fn i2sclk(mut self, hz: u32) -> Self {
+        self.i2sclk = Some(hz);
+        self
+    }
+
+    /// Works out the dividers, wait states and voltage scale for the
+    /// request. Being `const`, an impossible configuration in a constant
+    /// fails the build.
+    pub const fn freeze(self) -> Result<ClockPlan, ClockError> {
+        let source_hz = self.source.hz();
+        if let ClockSource::Hse(hz) = self.source
+            && (hz < HSE_RANGE.0 || hz > HSE_RANGE.1)
+        {
+            return Err(ClockError::HseOutOfRange);
+        }
+        let sysclk = match self.sysclk {
+            Some(hz) => hz,
+            None => source_hz,
+        };
+        if sysclk > SYSCLK_MAX {
+            return Err(ClockError::SysclkTooFast);
+        }
+
+        // Take 48 MHz from the main PLL if it runs anyway and its dividers
+        // allow, and from the PLLSAI otherwise.
+        let mut clock48 = None;
+        let pll = if sysclk == source_hz {
+            None
+        } else if let (true, Some(pll)) = (self.pll48, solve_pll(source_hz, sysclk, true)) {
+            clock48 = Some(Clock48Source::PllQ);
+            Some(pll)
+        } else {
+            match solve_pll(source_hz, sysclk, false) {
+                Some(pll) => Some(pll),
+                None => return Err(ClockError::NoPllSolution),
+            }
+        };
+        let pllsai = if self.pll48 && clock48.is_none() {
+            match solve_output(source_hz, PLL48_HZ, &PLLP_VALUES) {
+                Some((m, n, p)) => {
+                    clock48 = Some(Clock48Source::PllSaiP);
+                    Some(PllConfig {
+                        p,
+                        ..slowest_outputs(m, n)
+                    })
+                }
+                None => return Err(ClockError::NoPllSolution),
+            }
+        } else {
+            None
+        };
+        let plli2s = match self.i2sclk {
+            Some(hz) => match solve_output(source_hz, hz, &PLLR_VALUES) {
+                Some((m, n, r)) => Some(PllConfig {
+                    r,
+                    ..slowest_outputs(m, n)
+                }),
+                None => return Err(ClockError::NoPllI2sSolution),
+            },
+            None => None,
+        };
+
+        let hpre = match self.hclk {
+            Some(hclk) => match divisor(sysclk, hclk, &HPRE_VALUES) {
+                Some(hpre) => hpre,
+                None => return Err(ClockError::HclkUnreachable),
+            },
+            None => 1,
+        };
+        let hclk = sysclk / hpre;
+        let ppre1 = match apb_divisor(hclk, self.pclk1, PCLK1_MAX) {
+            Ok(ppre) => ppre,
+            Err(error) => return Err(error),
+        };
+        let ppre2 = match apb_divisor(hclk, self.pclk2, PCLK2_MAX) {
+            Ok(ppre) => ppre,
+            Err(error) => return Err(error),
+        };
+
+        let mut clocks = Clocks::new(sysclk, hpre, ppre1, ppre2);
+        if clock48.is_some() {
+            clocks.pll48clk = Some(PLL48_HZ);
+        }
+        clocks.i2sclk = self.i2sclk;
+
+        Ok(ClockPlan {
+            source: self.source,
+            pll,
+            pllsai,
+            plli2s,
+            clock48,
+            hpre,
+            ppre1,
+            ppre2,
+            flash_latency: ((hclk - 1) / FLASH_WS_HZ) as u8,
+            vos: VoltageScale::for_hclk(hclk),
+            overdrive: hclk > OVERDRIVE_ABOVE_HZ,
+            clocks,
+        })
+    }
+}
+
+impl Default for RccConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The entry of `divisors` that divides `from` down to exactly `to`.
+const fn divisor(from: u32, to: u32, divisors: &[u32]) -> Option<u32> {
+    let mut i = 0;
+    while i < divisors.len() {
+        if to != 0 && from / divisors[i] == to && from.is_multiple_of(divisors[i]) {
+            return Some(divisors[i]);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// APB divisor for `requested`, or the smallest one keeping under `max`.
+const fn apb_divisor(hclk: u32, requested: Option<u32>, max: u32) -> Result<u32, ClockError> {
+    if let Some(pclk) = requested {
+        if pclk > max {
+            return Err(ClockError::PclkTooFast);
+        }
+        return match divisor(hclk, pclk, &PPRE_VALUES) {
+            Some(ppre) => Ok(ppre),
+            None => Err(ClockError::PclkUnreachable),
+        };
+    }
+    let mut i = 0;
+    while i < PPRE_VALUES.len() {
+        if hclk / PPRE_VALUES[i] <= max {
+            return Ok(PPRE_VALUES[i]);
+        }
+        i += 1;
+    }
+    Err(ClockError::PclkTooFast)
+}
+
+/// Finds dividers giving exactly `sysclk`, and 48 MHz on Q if `pll48`.
+/// The smallest M comes first, so the VCO input is as close to the
+/// recommended 2 MHz as the source allows.
+const fn solve_pll(source_hz: u32, sysclk: u32, pll48: bool) -> Option<PllConfig> {
+    let source = source_hz as u64;
+    let mut m = PLLM_RANGE.0;
+    while m <= PLLM_RANGE.1 {
+        let vco_in_ok = source >= m as u64 * VCO_IN_RANGE.0 as u64
+            && source <= m as u64 * VCO_IN_RANGE.1 as u64;
+        let mut i = 0;
+        while vco_in_ok && i < PLLP_VALUES.len() {
+            let p = PLLP_VALUES[i];
+            let vco = sysclk as u64 * p as u64;
+            // VCO = source / M * N, so N has to come out whole.
+            let n = vco * m as u64 / source;
+            let n_ok = n * source == vco * m as u64
+                && n >= PLLN_RANGE.0 as u64
+                && n <= PLLN_RANGE.1 as u64;
+            let vco_ok = vco >= VCO_OUT_RANGE.0 as u64 && vco <= VCO_OUT_RANGE.1 as u64;
+            let q = if pll48 {
+                exact_q(vco)
+            } else {
+                // Keep the unused 48 MHz output within its limit.
+                Some(clamp(
+                    vco.div_ceil(PLL48_HZ as u64) as u32,
+                    PLLQ_RANGE.0,
+                    PLLQ_RANGE.1,
+                ))
+            };
+            if let (true, true, Some(q)) = (n_ok, vco_ok, q) {
+                return Some(PllConfig {
+                    m,
+                    n: n as u32,
+                    p,
+                    q,
+                    // Divide R like P, so nothing on it outruns SYSCLK.
+                    r: clamp(p, PLLR_RANGE.0, PLLR_RANGE.1),
+                });
+            }
+            i += 1;
+        }
+        m += 1;
+    }
+    None
+}
+
+/// M, N and the entry of `divisors` that give exactly `hz` on one output
+/// of a PLLSAI or PLLI2S, smallest M first as in [`solve_pll`].
+const fn solve_output(source_hz: u32, hz: u32, divisors: &[u32]) -> Option<(u32, u32, u32)> {
+    let source = source_hz as u64;
+    let mut m = PLLM_RANGE.0;
+    while m <= PLLM_RANGE.1 {
+        let vco_in_ok = source >= m as u64 * VCO_IN_RANGE.0 as u64
+            && source <= m as u64 * VCO_IN_RANGE.1 as u64;
+        let mut i = 0;
+        while vco_in_ok && i < divisors.len() {
+            let vco = hz as u64 * divisors[i] as u64;
+            let n = vco * m as u64 / source;
+            if n * source == vco * m as u64
+                && n >= PLLN_RANGE.0 as u64
+                && n <= PLLN_RANGE.1 as u64
+                && vco >= VCO_OUT_RANGE.0 as u64
+                && vco <= VCO_OUT_RANGE.1 as u64
+            {
+                return Some((m, n as u32, divisors[i]));
+            }
+            i += 1;
+        }
+        m += 1;
+    }
+    None
+}
+
+/// A PLLSAI or PLLI2S with every output divided as far as it goes, for
+/// the caller to speed up the one it uses.
+const fn slowest_outputs(m: u32, n: u32) -> PllConfig {
+    PllConfig {
+        m,
+        n,
+        p: PLLP_VALUES[PLLP_VALUES.len() - 1],
+        q: PLLQ_RANGE.1,
+        r: PLLR_RANGE.1,
+    }
+}
+
+/// Q divider giving exactly 48 MHz from `vco`.
+const fn exact_q(vco: u64) -> Option<u32> {
+    let q = vco / PLL48_HZ as u64;
+    if vco.is_multiple_of(PLL48_HZ as u64) && q >= PLLQ_RANGE.0 as u64 && q <= PLLQ_RANGE.1 as u64 {
+        Some(q as u32)
+    } else {
+        None
+    }
+}
+
+const fn clamp(value: u32, low: u32, high: u32) -> u32 {
+    if value < low {
+        low
+    } else if value > high {
+        high
+    } else {
+        value
+    }
+}
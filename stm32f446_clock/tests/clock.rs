@@ -0,0 +1,254 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use stm32f446_clock::{
+    Clock48Source, ClockError, ClockSource, ClockUsers, Clocks, HSI_TRIM_DEFAULT, LSE_HZ, LSI_HZ,
+    Measurement, PllConfig, RccConfig, VoltageScale, hsi_trim, timer_prescaler,
+};
+
+#[test]
+fn bus_clocks_divide_down_from_sysclk() {
+    let clocks = Clocks::new(180_000_000, 1, 4, 2);
+    assert_eq!(clocks.hclk(), 180_000_000);
+    assert_eq!(clocks.pclk1(), 45_000_000);
+    assert_eq!(clocks.pclk2(), 90_000_000);
+}
+
+#[test]
+fn timers_run_at_twice_a_divided_bus_clock() {
+    let clocks = Clocks::new(180_000_000, 1, 4, 1);
+    assert_eq!(clocks.timclk1(), 90_000_000);
+    assert_eq!(clocks.timclk2(), 180_000_000);
+
+    let hsi = Clocks::new(16_000_000, 1, 1, 1);
+    assert_eq!(hsi.timclk1(), 16_000_000);
+}
+
+#[test]
+fn prescaler_counts_at_the_requested_rate() {
+    assert_eq!(timer_prescaler(90_000_000, 1_000_000), 89);
+    assert_eq!(timer_prescaler(16_000_000, 10_000), 1599);
+}
+
+#[test]
+fn registered_users_hear_about_new_clocks() {
+    static SEEN: AtomicU32 = AtomicU32::new(0);
+    fn timer(clocks: &Clocks) {
+        SEEN.fetch_add(clocks.timclk1(), Ordering::Relaxed);
+    }
+
+    let mut users = ClockUsers::<2>::new();
+    assert!(users.register(timer));
+    assert!(users.register(timer));
+    assert!(!users.register(timer));
+
+    users.notify(&Clocks::new(16_000_000, 1, 1, 1));
+    assert_eq!(SEEN.load(Ordering::Relaxed), 32_000_000);
+}
+
+#[test]
+fn hse_at_180_mhz_solves_to_the_board_dividers() {
+    let plan = RccConfig::new()
+        .use_hse(8_000_000)
+        .sysclk(180_000_000)
+        .freeze()
+        .unwrap();
+    assert_eq!(
+        plan.pll,
+        Some(PllConfig {
+            m: 4,
+            n: 180,
+            p: 2,
+            q: 8,
+            r: 2
+        })
+    );
+    assert_eq!((plan.hpre, plan.ppre1, plan.ppre2), (1, 4, 2));
+    assert_eq!(plan.flash_latency, 5);
+    assert_eq!(plan.vos, VoltageScale::Scale1);
+    assert!(plan.overdrive);
+    assert_eq!(plan.clocks, Clocks::new(180_000_000, 1, 4, 2));
+}
+
+#[test]
+fn over_drive_is_only_needed_above_168_mhz() {
+    let hse = RccConfig::new().use_hse(8_000_000);
+    let plan = hse.sysclk(168_000_000).freeze().unwrap();
+    assert_eq!(plan.vos, VoltageScale::Scale1);
+    assert!(!plan.overdrive);
+
+    let divided = hse.sysclk(180_000_000).hclk(90_000_000).freeze().unwrap();
+    assert!(!divided.overdrive);
+    assert_eq!(divided.vos, VoltageScale::Scale3);
+}
+
+#[test]
+fn hsi_fallback_keeps_the_same_clocks() {
+    let plan = RccConfig::new()
+        .use_hse(8_000_000)
+        .sysclk(180_000_000)
+        .freeze()
+        .unwrap();
+    let fallback = plan.on_hsi().unwrap();
+    assert_eq!(fallback.source, ClockSource::Hsi);
+    assert_eq!(fallback.clocks, plan.clocks);
+    assert_eq!(
+        fallback.pll.map(|pll| (pll.m, pll.n, pll.p)),
+        Some((8, 180, 2))
+    );
+    assert_eq!(fallback.flash_latency, plan.flash_latency);
+}
+
+#[test]
+fn unset_clocks_run_straight_from_the_hsi() {
+    let plan = RccConfig::new().freeze().unwrap();
+    assert_eq!(plan.source, ClockSource::Hsi);
+    assert_eq!(plan.pll, None);
+    assert_eq!(plan.clocks.sysclk(), 16_000_000);
+    assert_eq!((plan.hpre, plan.ppre1, plan.ppre2), (1, 1, 1));
+    assert_eq!(plan.flash_latency, 0);
+    assert_eq!(plan.vos, VoltageScale::Scale3);
+    assert!(!plan.overdrive);
+}
+
+#[test]
+fn odd_crystal_still_reaches_the_vco_input_range() {
+    let plan = RccConfig::new()
+        .use_hse(25_000_000)
+        .sysclk(84_000_000)
+        .pclk1(42_000_000)
+        .freeze()
+        .unwrap();
+    let pll = plan.pll.unwrap();
+    assert_eq!((pll.m, pll.n, pll.p), (25, 168, 2));
+    assert_eq!(plan.ppre1, 2);
+    assert_eq!(plan.flash_latency, 2);
+}
+
+#[test]
+fn usb_clock_is_exactly_48_mhz() {
+    let plan = RccConfig::new()
+        .use_hse(8_000_000)
+        .sysclk(168_000_000)
+        .require_pll48clk()
+        .freeze()
+        .unwrap();
+    let pll = plan.pll.unwrap();
+    assert_eq!(8_000_000 / pll.m * pll.n / pll.q, 48_000_000);
+    assert_eq!(plan.clock48, Some(Clock48Source::PllQ));
+    assert_eq!(plan.pllsai, None);
+    assert_eq!(plan.clocks.pll48clk(), Some(48_000_000));
+}
+
+#[test]
+fn usb_clock_comes_from_the_pllsai_when_the_main_pll_cannot_give_it() {
+    let plan = RccConfig::new()
+        .use_hse(8_000_000)
+        .sysclk(180_000_000)
+        .require_pll48clk()
+        .freeze()
+        .unwrap();
+    assert_eq!(plan.clocks.sysclk(), 180_000_000);
+    assert_eq!(plan.clock48, Some(Clock48Source::PllSaiP));
+    let sai = plan.pllsai.unwrap();
+    assert_eq!(8_000_000 / sai.m * sai.n / sai.p, 48_000_000);
+    assert_eq!(plan.clocks.pll48clk(), Some(48_000_000));
+
+    let fallback = plan.on_hsi().unwrap();
+    let sai = fallback.pllsai.unwrap();
+    assert_eq!(16_000_000 / sai.m * sai.n / sai.p, 48_000_000);
+}
+
+#[test]
+fn i2s_clock_comes_from_the_plli2s() {
+    let plan = RccConfig::new()
+        .use_hse(8_000_000)
+        .sysclk(180_000_000)
+        .i2sclk(61_440_000)
+        .freeze()
+        .unwrap();
+    let i2s = plan.plli2s.unwrap();
+    assert_eq!(
+        8_000_000 * u64::from(i2s.n) / u64::from(i2s.m * i2s.r),
+        61_440_000
+    );
+    assert_eq!(plan.clocks.i2sclk(), Some(61_440_000));
+    assert_eq!(plan.clocks.pll48clk(), None);
+
+    let plain = RccConfig::new().use_hse(8_000_000).sysclk(180_000_000);
+    assert_eq!(
+        plain.i2sclk(61_440_001).freeze(),
+        Err(ClockError::NoPllI2sSolution)
+    );
+}
+
+#[test]
+fn impossible_requests_are_errors() {
+    let hse = RccConfig::new().use_hse(8_000_000);
+    assert_eq!(
+        hse.sysclk(200_000_000).freeze(),
+        Err(ClockError::SysclkTooFast)
+    );
+    assert_eq!(
+        RccConfig::new().use_hse(30_000_000).freeze(),
+        Err(ClockError::HseOutOfRange)
+    );
+    assert_eq!(
+        hse.sysclk(180_000_000).hclk(60_000_000).freeze(),
+        Err(ClockError::HclkUnreachable)
+    );
+    assert_eq!(
+        hse.sysclk(180_000_000).pclk1(90_000_000).freeze(),
+        Err(ClockError::PclkTooFast)
+    );
+    assert_eq!(
+        hse.sysclk(180_000_000).pclk2(60_000_000).freeze(),
+        Err(ClockError::PclkUnreachable)
+    );
+    assert_eq!(
+        hse.sysclk(179_999_999).freeze(),
+        Err(ClockError::NoPllSolution)
+    );
+}
+
+#[test]
+fn timer_clock_is_measured_against_the_lse() {
+    // 90 MHz counted across 8 LSE cycles.
+    let ticks = 8 * 90_000_000 / LSE_HZ;
+    let timer = Measurement::of_counter(90_000_000, LSE_HZ, 8, ticks).unwrap();
+    assert!(timer.within_ppm(100));
+
+    let fast = Measurement::of_counter(90_000_000, LSE_HZ, 8, ticks + ticks / 100).unwrap();
+    assert!((9_900..=10_100).contains(&fast.error_ppm()));
+    assert!(!fast.within_ppm(1000));
+}
+
+#[test]
+fn lsi_is_measured_against_the_timer_clock() {
+    let lsi = Measurement::of_input(LSI_HZ, 90_000_000, 8, 8 * 90_000_000 / 34_000).unwrap();
+    assert_eq!(lsi.measured / 100, 340);
+    assert_eq!(lsi.error_ppm() / 10_000, 6);
+}
+
+#[test]
+fn empty_capture_measures_nothing() {
+    assert_eq!(Measurement::of_counter(90_000_000, LSE_HZ, 8, 0), None);
+    assert_eq!(Measurement::of_counter(90_000_000, LSE_HZ, 0, 100), None);
+    assert_eq!(Measurement::of_input(LSI_HZ, 90_000_000, 8, 0), None);
+    assert_eq!(Measurement::of_input(LSI_HZ, 90_000_000, 0, 100), None);
+}
+
+#[test]
+fn hsi_trim_steps_towards_16_mhz() {
+    let slow = Measurement {
+        nominal: 16_000_000,
+        measured: 15_840_000,
+    };
+    assert_eq!(hsi_trim(HSI_TRIM_DEFAULT, &slow), HSI_TRIM_DEFAULT + 2);
+
+    let fast = Measurement {
+        nominal: 16_000_000,
+        measured: 16_050_000,
+    };
+    assert_eq!(hsi_trim(HSI_TRIM_DEFAULT, &fast), HSI_TRIM_DEFAULT - 1);
+    assert_eq!(hsi_trim(0, &fast), 0);
+    assert_eq!(hsi_trim(31, &slow), 31);
+}